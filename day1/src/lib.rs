@@ -1,79 +1,221 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+const NUMERALS: [&str; 10] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+
 const DIGITS: [&str; 10] = [
     "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
 ];
 
-fn parse_ascii_digit(digit: char) -> u32 {
-    assert!(digit.is_ascii_digit());
-    (digit as u8 - b'0') as u32
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VocabularyError {
+    NoWords,
+    EmptyWord,
+    DuplicateWord(String),
 }
 
-fn parse_digit(string: &str) -> Option<u32> {
-    // If numeric digit
-    let first_char = string.chars().next().expect("Empty window");
-    if first_char.is_ascii_digit() {
-        return Some(parse_ascii_digit(first_char));
+impl fmt::Display for VocabularyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VocabularyError::NoWords => write!(f, "vocabulary has no words"),
+            VocabularyError::EmptyWord => write!(f, "vocabulary contains an empty word"),
+            VocabularyError::DuplicateWord(word) => {
+                write!(f, "word {:?} is given twice", word)
+            }
+        }
     }
+}
+
+impl std::error::Error for VocabularyError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    NoDigits(String),
+}
 
-    for (i, digit) in DIGITS.iter().enumerate() {
-        if string.starts_with(digit) {
-            return Some(i as u32);
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::NoDigits(line) => write!(f, "no digits in line {:?}", line),
         }
     }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A word of the vocabulary matched at the end of a state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Output {
+    len: usize,
+    value: u32,
+}
 
-    None
+/// A match of a word in a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Match {
+    start: usize,
+    len: usize,
+    value: u32,
 }
 
-fn find_first_digit(string: &str) -> u32 {
-    for (i, _) in string.char_indices() {
-        let substr = &string[i..];
-        if let Some(digit) = parse_digit(substr) {
-            return digit;
+/// Aho-Corasick automaton finding every occurrence of a vocabulary of words, including
+/// ones that overlap.
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    /// Next state for every byte from each state, with failures already followed.
+    transitions: Vec<[u32; 256]>,
+    /// Longest and shortest words that end on reaching each state.
+    longest: Vec<Option<Output>>,
+    shortest: Vec<Option<Output>>,
+}
+
+impl Matcher {
+    /// Builds a matcher for words with the value each one stands for.
+    pub fn new<'a>(
+        words: impl IntoIterator<Item = (&'a str, u32)>,
+    ) -> Result<Self, VocabularyError> {
+        // Trie of the words, with 0 as the missing transition as the root is never a child
+        let mut transitions = vec![[0; 256]];
+        let mut longest: Vec<Option<Output>> = vec![None];
+
+        for (word, value) in words {
+            if word.is_empty() {
+                return Err(VocabularyError::EmptyWord);
+            }
+
+            let mut state = 0;
+            for &byte in word.as_bytes() {
+                if transitions[state][byte as usize] == 0 {
+                    transitions[state][byte as usize] = transitions.len() as u32;
+                    transitions.push([0; 256]);
+                    longest.push(None);
+                }
+                state = transitions[state][byte as usize] as usize;
+            }
+
+            if longest[state].is_some() {
+                return Err(VocabularyError::DuplicateWord(word.to_string()));
+            }
+            longest[state] = Some(Output {
+                len: word.len(),
+                value,
+            });
+        }
+        if transitions.len() == 1 {
+            return Err(VocabularyError::NoWords);
+        }
+
+        // Breadth first so that the failure of each state is complete before its children
+        let mut shortest = longest.clone();
+        let mut failure = vec![0; transitions.len()];
+        let mut queue: VecDeque<_> = transitions[0]
+            .iter()
+            .filter(|&&child| child != 0)
+            .map(|&child| child as usize)
+            .collect();
+
+        while let Some(state) = queue.pop_front() {
+            // A word ending at the failure state is a suffix of the words ending here
+            let fallback = failure[state];
+            longest[state] = longest[state].or(longest[fallback]);
+            shortest[state] = shortest[fallback].or(shortest[state]);
+
+            let fallback_transitions = transitions[fallback];
+            for (byte, &next) in fallback_transitions.iter().enumerate() {
+                let child = transitions[state][byte] as usize;
+                if child == 0 {
+                    transitions[state][byte] = next;
+                } else {
+                    failure[child] = next as usize;
+                    queue.push_back(child);
+                }
+            }
         }
+
+        Ok(Self {
+            transitions,
+            longest,
+            shortest,
+        })
+    }
+
+    /// Matches the numerals 0 to 9, as in part 1.
+    pub fn numerals() -> Self {
+        Self::new(NUMERALS.into_iter().zip(0..)).unwrap()
+    }
+
+    /// Matches the numerals 0 to 9 and their names in English, as in part 2.
+    pub fn english() -> Self {
+        Self::new(
+            NUMERALS
+                .into_iter()
+                .zip(0..)
+                .chain(DIGITS.into_iter().zip(0..)),
+        )
+        .unwrap()
     }
-    panic!()
-}
 
-fn find_last_digit(string: &str) -> u32 {
-    for (i, _) in string.char_indices().rev() {
-        let substr = &string[i..];
-        if let Some(digit) = parse_digit(substr) {
-            return digit;
+    /// Values of the words starting first and last in a line, preferring the longest word
+    /// when several start at the same place.
+    pub fn first_and_last(&self, line: &str) -> Option<(u32, u32)> {
+        let mut first: Option<Match> = None;
+        let mut last: Option<Match> = None;
+        let to_match = |output: Output, end: usize| Match {
+            start: end - output.len,
+            len: output.len,
+            value: output.value,
+        };
+
+        let mut state = 0;
+        for (i, &byte) in line.as_bytes().iter().enumerate() {
+            state = self.transitions[state][byte as usize] as usize;
+
+            if let Some(output) = self.longest[state] {
+                let found = to_match(output, i + 1);
+                if first.is_none_or(|first| (found.start, !found.len) < (first.start, !first.len)) {
+                    first = Some(found);
+                }
+            }
+            if let Some(output) = self.shortest[state] {
+                // Later matches from the same start are always longer
+                let found = to_match(output, i + 1);
+                if last.is_none_or(|last| found.start >= last.start) {
+                    last = Some(found);
+                }
+            }
         }
+
+        Some((first?.value, last?.value))
+    }
+
+    /// The value of the first word followed by the value of the last.
+    pub fn calibration_value(&self, line: &str) -> Result<u32, ParseError> {
+        let (first, last) = self
+            .first_and_last(line)
+            .ok_or_else(|| ParseError::NoDigits(line.to_string()))?;
+
+        Ok(first * 10 + last)
     }
-    panic!()
 }
 
-pub fn part1(input: &str) -> String {
+/// Sum of the calibration values of each non-empty line.
+pub fn calibration_sum(input: &str, matcher: &Matcher) -> Result<u32, ParseError> {
     input
         .lines()
         .filter(|line| !line.is_empty())
-        .map(|line| {
-            // Get digits
-            let digits = || line.chars().filter(|c| c.is_ascii_digit());
-
-            let first = digits().next().expect("No digits");
-            let last = digits().last().expect("No remaining digits");
+        .map(|line| matcher.calibration_value(line))
+        .sum()
+}
 
-            // Convert to numbers
-            let first = parse_ascii_digit(first);
-            let last = parse_ascii_digit(last);
-            first * 10 + last
-        })
-        .sum::<u32>()
+pub fn part1(input: &str) -> String {
+    calibration_sum(input, &Matcher::numerals())
+        .expect("Invalid calibration document")
         .to_string()
 }
 
 pub fn part2(input: &str) -> String {
-    input
-        .lines()
-        .filter(|line| !line.is_empty())
-        .map(|line| {
-            let first = find_first_digit(line);
-            let last = find_last_digit(line);
-
-            first * 10 + last
-        })
-        .sum::<u32>()
+    calibration_sum(input, &Matcher::english())
+        .expect("Invalid calibration document")
         .to_string()
 }
 
@@ -107,4 +249,78 @@ zoneight234
 
         assert_eq!(&part2(input), "281");
     }
+
+    #[test]
+    fn test_overlaps() {
+        let matcher = Matcher::english();
+        assert_eq!(matcher.first_and_last("eightwo"), Some((8, 2)));
+        assert_eq!(matcher.first_and_last("oneight"), Some((1, 8)));
+        assert_eq!(matcher.first_and_last("twone"), Some((2, 1)));
+        assert_eq!(matcher.first_and_last("nineight"), Some((9, 8)));
+        assert_eq!(matcher.first_and_last("sevenine"), Some((7, 9)));
+        assert_eq!(matcher.first_and_last("xfivex"), Some((5, 5)));
+        assert_eq!(matcher.first_and_last("fivfive"), Some((5, 5)));
+    }
+
+    #[test]
+    fn test_vocabularies() {
+        // Teens start with the name of a digit
+        let teens = [
+            "ten",
+            "eleven",
+            "twelve",
+            "thirteen",
+            "fourteen",
+            "fifteen",
+            "sixteen",
+            "seventeen",
+            "eighteen",
+            "nineteen",
+        ];
+        let matcher = Matcher::new(
+            NUMERALS
+                .into_iter()
+                .zip(0..)
+                .chain(DIGITS.into_iter().zip(0..))
+                .chain(teens.into_iter().zip(10..)),
+        )
+        .unwrap();
+        assert_eq!(matcher.first_and_last("7pqrstsixteen"), Some((7, 16)));
+        assert_eq!(matcher.first_and_last("sixteen"), Some((16, 16)));
+        assert_eq!(matcher.first_and_last("eighteenineteen"), Some((18, 19)));
+        assert_eq!(matcher.first_and_last("fourteensix"), Some((14, 6)));
+
+        let french = [
+            "zéro", "un", "deux", "trois", "quatre", "cinq", "six", "sept", "huit", "neuf",
+        ];
+        let matcher = Matcher::new(french.into_iter().zip(0..)).unwrap();
+        assert_eq!(matcher.calibration_value("troisept"), Ok(37));
+        assert_eq!(matcher.calibration_value("zérhuitzéro"), Ok(80));
+        assert_eq!(
+            calibration_sum("deuxneuf\nunseptrois\n", &matcher),
+            Ok(29 + 13)
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Matcher::new([("one", 1), ("", 0)]).unwrap_err(),
+            VocabularyError::EmptyWord
+        );
+        assert_eq!(
+            Matcher::new([("one", 1), ("one", 2)]).unwrap_err(),
+            VocabularyError::DuplicateWord("one".to_string())
+        );
+        assert_eq!(Matcher::new([]).unwrap_err(), VocabularyError::NoWords);
+
+        assert_eq!(
+            calibration_sum("1abc2\nabc\n", &Matcher::numerals()),
+            Err(ParseError::NoDigits("abc".to_string()))
+        );
+        assert_eq!(
+            Matcher::numerals().calibration_value("one"),
+            Err(ParseError::NoDigits("one".to_string()))
+        );
+    }
 }
@@ -1,110 +1,67 @@
 #[derive(Debug)]
 struct Image {
-    galaxies: Vec<(usize, usize)>,
-    width: usize,
-    height: usize,
+    /// Number of galaxies in each column.
+    columns: Vec<u64>,
+    /// Number of galaxies in each row.
+    rows: Vec<u64>,
 }
 
 impl Image {
     fn new(input: &str) -> Self {
-        let data = || input.trim().lines().flat_map(|line| line.chars());
-
         let width = input.trim().lines().next().unwrap().len();
-        let height = data().count() / width;
-
-        let galaxies: Vec<_> = data()
-            .enumerate()
-            .filter_map(|(i, d)| {
-                if d == '#' {
-                    Some((i % width, i / width))
-                } else {
-                    None
-                }
-            })
-            .collect();
-
-        Self {
-            galaxies,
-            width,
-            height,
+        let mut columns = vec![0; width];
+        let mut rows = Vec::new();
+
+        for line in input.trim().lines() {
+            let mut count = 0;
+            for (x, _) in line.bytes().enumerate().filter(|(_, d)| *d == b'#') {
+                columns[x] += 1;
+                count += 1;
+            }
+            rows.push(count);
         }
-    }
 
-    fn row(&self, index: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
-        self.galaxies
-            .iter()
-            .filter(move |(_, y)| index == *y)
-            .copied()
+        Self { columns, rows }
     }
+}
 
-    fn column(&self, index: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
-        self.galaxies
-            .iter()
-            .filter(move |(x, _)| index == *x)
-            .copied()
-    }
+/// Sum of distances along one axis between every pair of galaxies, given the number of
+/// galaxies at each index.
+///
+/// Visiting the indices in order keeps the expanded coordinates sorted, so each galaxy is
+/// the far end of a pair with every galaxy before it, adding `count * coordinate - sum` to
+/// the total, where `count` and `sum` are the number and sum of the coordinates so far.
+fn axis_distances(counts: &[u64], expansion: u64) -> u128 {
+    let mut empty = 0;
+    let mut count = 0;
+    let mut sum = 0;
+    let mut total = 0;
 
-    fn insert_rows(&mut self, index: usize, count: usize) {
-        for (_, y) in self.galaxies.iter_mut() {
-            if *y >= index {
-                *y += count;
-            }
+    for (i, &galaxies) in counts.iter().enumerate() {
+        if galaxies == 0 {
+            empty += 1;
+            continue;
         }
-        self.height += count;
-    }
 
-    fn insert_columns(&mut self, index: usize, count: usize) {
-        for (x, _) in self.galaxies.iter_mut() {
-            if *x >= index {
-                *x += count;
-            }
-        }
-        self.width += count;
-    }
+        // Each empty line before this one takes up `expansion` lines instead of one
+        let coordinate = (i as u128 - empty) + empty * expansion as u128;
+        let galaxies = galaxies as u128;
 
-    fn expand(&mut self, factor: usize) {
-        // Find empty rows and columns
-        let empty_rows: Vec<_> = (0..self.height)
-            .filter(|row| self.row(*row).count() == 0)
-            .collect();
-        let empty_cols: Vec<_> = (0..self.width)
-            .filter(|col| self.column(*col).count() == 0)
-            .collect();
-
-        // Expand
-        for row in empty_rows.iter().rev() {
-            self.insert_rows(*row, factor);
-        }
-        for col in empty_cols.iter().rev() {
-            self.insert_columns(*col, factor);
-        }
+        // Galaxies on the same line are distance 0 apart
+        total += galaxies * (count * coordinate - sum);
+        count += galaxies;
+        sum += galaxies * coordinate;
     }
-}
-
-fn distance(a: (usize, usize), b: (usize, usize)) -> usize {
-    let (x1, y1) = a;
-    let (x2, y2) = b;
 
-    (x2 as isize - x1 as isize).unsigned_abs() + (y2 as isize - y1 as isize).unsigned_abs()
+    total
 }
 
-fn sum_of_distances(input: &str, expansion: usize) -> usize {
-    let mut image = Image::new(input);
+/// Sum of the shortest distances between every pair of galaxies, after each empty row and
+/// column is replaced by `expansion` of them.
+pub fn sum_of_distances(input: &str, expansion: u64) -> u128 {
+    let image = Image::new(input);
 
-    // Adjust for exapansion
-    image.expand(expansion - 1);
-
-    // For each pair of galaxies
-    let mut sum = 0;
-    for i in 0..image.galaxies.len() {
-        for j in (i + 1)..image.galaxies.len() {
-            let g1 = image.galaxies[i];
-            let g2 = image.galaxies[j];
-
-            sum += distance(g1, g2);
-        }
-    }
-    sum
+    axis_distances(&image.columns, expansion) + axis_distances(&image.rows, expansion)
 }
 
 pub fn part1(input: &str) -> String {
@@ -132,6 +89,38 @@ mod tests {
 #...#.....
 "#;
 
+    fn distance(a: (usize, usize), b: (usize, usize)) -> usize {
+        let (x1, y1) = a;
+        let (x2, y2) = b;
+
+        (x2 as isize - x1 as isize).unsigned_abs() + (y2 as isize - y1 as isize).unsigned_abs()
+    }
+
+    /// Expands the image explicitly and adds up the distance between every pair.
+    fn brute_force(input: &str, expansion: usize) -> u128 {
+        let lines: Vec<_> = input.trim().lines().map(str::as_bytes).collect();
+        let empty_row = |y: usize| lines[y].iter().all(|&d| d != b'#');
+        let empty_column = |x: usize| lines.iter().all(|line| line[x] != b'#');
+        let expand = |i: usize, empty: &dyn Fn(usize) -> bool| {
+            (0..i).map(|j| if empty(j) { expansion } else { 1 }).sum()
+        };
+
+        let mut galaxies = Vec::new();
+        for (y, line) in lines.iter().enumerate() {
+            for (x, _) in line.iter().enumerate().filter(|(_, d)| **d == b'#') {
+                galaxies.push((expand(x, &empty_column), expand(y, &empty_row)));
+            }
+        }
+
+        let mut sum = 0;
+        for i in 0..galaxies.len() {
+            for j in (i + 1)..galaxies.len() {
+                sum += distance(galaxies[i], galaxies[j]) as u128;
+            }
+        }
+        sum
+    }
+
     #[test]
     fn test_distance() {
         assert_eq!(distance((1, 6), (5, 11)), 9);
@@ -140,6 +129,33 @@ mod tests {
         assert_eq!(distance((0, 11), (5, 11)), 5);
     }
 
+    #[test]
+    fn test_brute_force() {
+        const STACKED_INPUT: &str = "#..#\n....\n#..#\n...#\n";
+
+        for expansion in [0, 1, 2, 7, 100] {
+            assert_eq!(
+                sum_of_distances(TEST_INPUT, expansion as u64),
+                brute_force(TEST_INPUT, expansion)
+            );
+            assert_eq!(
+                sum_of_distances(STACKED_INPUT, expansion as u64),
+                brute_force(STACKED_INPUT, expansion)
+            );
+        }
+    }
+
+    #[test]
+    fn test_large_expansion() {
+        // Distances grow linearly with the expansion
+        let base = sum_of_distances(TEST_INPUT, 1);
+        let step = sum_of_distances(TEST_INPUT, 2) - base;
+        assert_eq!(
+            sum_of_distances(TEST_INPUT, u64::MAX),
+            base + step * (u64::MAX as u128 - 1)
+        );
+    }
+
     #[test]
     fn test_part1() {
         assert_eq!(&part1(TEST_INPUT), "374");
@@ -1,151 +1,168 @@
-struct Map {
-    tiles: Vec<char>,
-    width: usize,
-    height: usize,
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    RaggedRow { y: usize, len: usize, width: usize },
+    UnknownTile { x: usize, y: usize, tile: char },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "pattern is empty"),
+            ParseError::RaggedRow { y, len, width } => {
+                write!(f, "row {} has length {}, expected {}", y, len, width)
+            }
+            ParseError::UnknownTile { x, y, tile } => {
+                write!(f, "unknown tile {:?} at ({}, {})", tile, x, y)
+            }
+        }
+    }
 }
 
-impl Map {
-    fn new(input: &str) -> Self {
-        let tiles: Vec<_> = input.trim().lines().flat_map(|line| line.chars()).collect();
+impl std::error::Error for ParseError {}
 
-        let width = input.trim().lines().next().unwrap().len();
-        let height = tiles.len() / width;
-        assert_eq!(width * height, tiles.len());
+/// Fixed length lines of bits, packed into `u64` words.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Lines {
+    words: Vec<u64>,
+    words_per_line: usize,
+}
 
+impl Lines {
+    fn new(lines: usize, len: usize) -> Self {
+        let words_per_line = len.div_ceil(64);
         Self {
-            tiles,
-            width,
-            height,
+            words: vec![0; lines * words_per_line],
+            words_per_line,
         }
     }
 
-    fn at(&mut self, x: usize, y: usize) -> &mut char {
-        let index = (y - 1) * self.width + (x - 1);
-        &mut self.tiles[index]
+    fn len(&self) -> usize {
+        self.words.len() / self.words_per_line
     }
 
-    fn row(&self, index: usize) -> impl Iterator<Item = char> + '_ {
-        self.tiles
-            .iter()
-            .skip((index - 1) * self.width)
-            .take(self.width)
-            .copied()
+    fn line(&self, i: usize) -> &[u64] {
+        &self.words[i * self.words_per_line..(i + 1) * self.words_per_line]
     }
 
-    fn column(&self, index: usize) -> impl Iterator<Item = char> + '_ {
-        self.tiles
+    fn insert(&mut self, line: usize, bit: usize) {
+        self.words[line * self.words_per_line + bit / 64] |= 1 << (bit % 64);
+    }
+
+    /// Number of bits that differ between lines `a` and `b`.
+    fn differences(&self, a: usize, b: usize) -> u32 {
+        self.line(a)
             .iter()
-            .skip(index - 1)
-            .step_by(self.width)
-            .copied()
+            .zip(self.line(b))
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum()
     }
 }
 
-fn find_reflections(map: &Map, is_horizontal: bool) -> Vec<usize> {
-    let mut reflections = Vec::new();
-    let dimension = if is_horizontal { map.height } else { map.width };
+/// A pattern with every row and column packed into a bitmask of its rocks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    rows: Lines,
+    columns: Lines,
+}
 
-    for start_left in 1..dimension {
-        let start_right = start_left + 1;
+impl Pattern {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let lines: Vec<_> = input.trim().lines().collect();
+        let height = lines.len();
+        let width = lines.first().map_or(0, |line| line.chars().count());
+        if width == 0 {
+            return Err(ParseError::Empty);
+        }
 
-        let mut left = start_left;
-        let mut right = start_right;
+        let mut rows = Lines::new(height, width);
+        let mut columns = Lines::new(width, height);
 
-        while left > 0 && right < dimension + 1 {
-            let is_equal = if is_horizontal {
-                map.row(left).eq(map.row(right))
-            } else {
-                map.column(left).eq(map.column(right))
-            };
+        for (y, line) in lines.iter().enumerate() {
+            let len = line.chars().count();
+            if len != width {
+                return Err(ParseError::RaggedRow { y, len, width });
+            }
 
-            if is_equal {
-                left -= 1;
-                right += 1;
-            } else {
-                break;
+            for (x, tile) in line.chars().enumerate() {
+                match tile {
+                    '#' => {
+                        rows.insert(y, x);
+                        columns.insert(x, y);
+                    }
+                    '.' => {}
+                    _ => return Err(ParseError::UnknownTile { x, y, tile }),
+                }
             }
         }
 
-        if left == 0 || right == dimension + 1 {
-            reflections.push(start_left);
-        }
+        Ok(Self { rows, columns })
     }
 
-    reflections
-}
-
-fn find_horizontal_reflections(map: &Map) -> Vec<usize> {
-    find_reflections(map, true)
+    /// Finds the line of reflection for which exactly `smudges` cells differ from their
+    /// mirror image, checking vertical lines before horizontal ones.
+    pub fn find_reflection(&self, smudges: u32) -> Option<Reflection> {
+        if let Some(index) = find_reflection(&self.columns, smudges) {
+            Some(Reflection::Vertical(index))
+        } else {
+            find_reflection(&self.rows, smudges).map(Reflection::Horizontal)
+        }
+    }
 }
 
-fn find_vertical_reflections(map: &Map) -> Vec<usize> {
-    find_reflections(map, false)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reflection {
+    /// Reflected between columns, with this many columns to the left.
+    Vertical(usize),
+    /// Reflected between rows, with this many rows above.
+    Horizontal(usize),
 }
 
-pub fn part1(input: &str) -> String {
-    let mut sum_left_columns = 0;
-    let mut sum_above_rows = 0;
-
-    for map in input.trim().split("\n\n") {
-        let map = Map::new(map);
-
-        if let Some(index) = find_vertical_reflections(&map).first() {
-            sum_left_columns += *index as u32;
-        } else if let Some(index) = find_horizontal_reflections(&map).first() {
-            sum_above_rows += *index as u32;
+impl Reflection {
+    pub fn summary(&self) -> usize {
+        match self {
+            Reflection::Vertical(index) => *index,
+            Reflection::Horizontal(index) => 100 * index,
         }
     }
-
-    (sum_left_columns + (100 * sum_above_rows)).to_string()
 }
 
-fn find_smudge_reflection(map: &mut Map) -> (usize, usize) {
-    let original_vertical = find_vertical_reflections(map).first().copied().unwrap_or(0);
-    let original_horizontal = find_horizontal_reflections(map)
-        .first()
-        .copied()
-        .unwrap_or(0);
-
-    for y in 1..=map.height {
-        for x in 1..=map.width {
-            let original = *map.at(x, y);
-            let flipped = if original == '#' { '.' } else { '#' };
-
-            // Flip
-            *map.at(x, y) = flipped;
-
-            for index in find_vertical_reflections(map) {
-                if index != original_vertical {
-                    return (index, 0);
-                }
+fn find_reflection(lines: &Lines, smudges: u32) -> Option<usize> {
+    (1..lines.len()).find(|&split| {
+        let mut differences = 0;
+        for (a, b) in (0..split).rev().zip(split..lines.len()) {
+            differences += lines.differences(a, b);
+            if differences > smudges {
+                return false;
             }
-
-            for index in find_horizontal_reflections(map) {
-                if index != original_horizontal {
-                    return (0, index);
-                }
-            }
-
-            // Reset
-            *map.at(x, y) = original;
         }
-    }
+        differences == smudges
+    })
+}
 
-    panic!("No reflection")
+/// Sums the reflection summaries of every pattern, allowing `smudges` cells to differ.
+pub fn summarise(input: &str, smudges: u32) -> usize {
+    input
+        .trim()
+        .split("\n\n")
+        .map(|pattern| {
+            Pattern::parse(pattern)
+                .expect("Invalid pattern")
+                .find_reflection(smudges)
+                .expect("No reflection")
+                .summary()
+        })
+        .sum()
 }
 
-pub fn part2(input: &str) -> String {
-    let mut sum_left_columns = 0;
-    let mut sum_above_rows = 0;
-
-    for map in input.trim().split("\n\n") {
-        let mut map = Map::new(map);
-        let (vertical, horizontal) = find_smudge_reflection(&mut map);
-        sum_left_columns += vertical as u32;
-        sum_above_rows += horizontal as u32;
-    }
+pub fn part1(input: &str) -> String {
+    summarise(input, 0).to_string()
+}
 
-    (sum_left_columns + (100 * sum_above_rows)).to_string()
+pub fn part2(input: &str) -> String {
+    summarise(input, 1).to_string()
 }
 
 #[cfg(test)]
@@ -179,4 +196,63 @@ mod tests {
     fn test_part2() {
         assert_eq!(&part2(TEST_INPUT), "400");
     }
+
+    #[test]
+    fn test_find_reflection() {
+        let patterns: Vec<_> = TEST_INPUT
+            .trim()
+            .split("\n\n")
+            .map(|pattern| Pattern::parse(pattern).unwrap())
+            .collect();
+
+        assert_eq!(
+            patterns[0].find_reflection(0),
+            Some(Reflection::Vertical(5))
+        );
+        assert_eq!(
+            patterns[1].find_reflection(0),
+            Some(Reflection::Horizontal(4))
+        );
+        assert_eq!(
+            patterns[0].find_reflection(1),
+            Some(Reflection::Horizontal(3))
+        );
+        assert_eq!(
+            patterns[1].find_reflection(1),
+            Some(Reflection::Horizontal(1))
+        );
+
+        let pattern = Pattern::parse("##.\n...\n").unwrap();
+        assert_eq!(pattern.find_reflection(0), Some(Reflection::Vertical(1)));
+        assert_eq!(pattern.find_reflection(1), Some(Reflection::Vertical(2)));
+        assert_eq!(pattern.find_reflection(2), Some(Reflection::Horizontal(1)));
+        assert_eq!(pattern.find_reflection(3), None);
+    }
+
+    #[test]
+    fn test_large_pattern() {
+        // 100 columns mirrored between columns 70 and 71, over 130 rows
+        let row = |y: usize| -> String {
+            (0..100)
+                .map(|x: usize| {
+                    let x = if x < 70 { x } else { 139 - x };
+                    if (x * 7 + y * 3) % 5 < 2 {
+                        '#'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect()
+        };
+        let rows: Vec<_> = (0..130).map(row).collect();
+        let pattern = Pattern::parse(&rows.join("\n")).unwrap();
+        assert_eq!(pattern.find_reflection(0), Some(Reflection::Vertical(70)));
+
+        // Transposed, the same line of reflection is horizontal
+        let columns: Vec<String> = (0..100)
+            .map(|x| rows.iter().map(|row| &row[x..x + 1]).collect())
+            .collect();
+        let pattern = Pattern::parse(&columns.join("\n")).unwrap();
+        assert_eq!(pattern.find_reflection(0), Some(Reflection::Horizontal(70)));
+    }
 }
@@ -1,156 +1,277 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct Map {
-    tiles: Vec<char>,
-    width: usize,
-    height: usize,
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    RaggedRow { y: usize, len: usize, width: usize },
+    UnknownTile { x: usize, y: usize, tile: char },
 }
 
-impl Map {
-    fn new(input: &str) -> Self {
-        let tiles: Vec<_> = input.trim().lines().flat_map(|line| line.chars()).collect();
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "platform is empty"),
+            ParseError::RaggedRow { y, len, width } => {
+                write!(f, "row {} has length {}, expected {}", y, len, width)
+            }
+            ParseError::UnknownTile { x, y, tile } => {
+                write!(f, "unknown tile {:?} at ({}, {})", tile, x, y)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
 
-        let width = input.trim().lines().next().unwrap().len();
-        let height = tiles.len() / width;
-        assert_eq!(width * height, tiles.len());
+/// Fixed length lines of bits, packed into `u64` words.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Lines {
+    words: Vec<u64>,
+    words_per_line: usize,
+}
 
+impl Lines {
+    fn new(lines: usize, len: usize) -> Self {
+        let words_per_line = len.div_ceil(64);
         Self {
-            tiles,
-            width,
-            height,
+            words: vec![0; lines * words_per_line],
+            words_per_line,
         }
     }
 
-    fn at(&self, x: usize, y: usize) -> char {
-        let index = y * self.width + x;
-        self.tiles[index]
+    fn line(&self, i: usize) -> &[u64] {
+        &self.words[i * self.words_per_line..(i + 1) * self.words_per_line]
     }
 
-    fn at_mut(&mut self, x: usize, y: usize) -> &mut char {
-        let index = y * self.width + x;
-        &mut self.tiles[index]
+    fn line_mut(&mut self, i: usize) -> &mut [u64] {
+        &mut self.words[i * self.words_per_line..(i + 1) * self.words_per_line]
     }
-}
 
-fn slide_north(map: &mut Map) {
-    for start_y in 1..map.height {
-        for x in 0..map.width {
-            let mut y = start_y;
-            while map.at(x, y) == 'O' && map.at(x, y - 1) == '.' {
-                *map.at_mut(x, y) = '.';
-                *map.at_mut(x, y - 1) = 'O';
+    fn insert(&mut self, line: usize, bit: usize) {
+        self.line_mut(line)[bit / 64] |= 1 << (bit % 64);
+    }
 
-                if y > 1 {
-                    y -= 1;
-                }
+    fn clear(&mut self) {
+        self.words.fill(0);
+    }
+
+    /// Transposes the set bits of `self` into `other`, which must have as many lines as
+    /// `self` has bits per line.
+    fn transpose_into(&self, other: &mut Lines) {
+        other.clear();
+        for (i, word) in self.words.iter().enumerate() {
+            let line = i / self.words_per_line;
+            let base = (i % self.words_per_line) * 64;
+
+            let mut word = *word;
+            while word != 0 {
+                let bit = word.trailing_zeros() as usize;
+                other.insert(base + bit, line);
+                word &= word - 1;
             }
         }
     }
 }
 
-fn slide_east(map: &mut Map) {
-    for start_x in (0..(map.width - 1)).rev() {
-        for y in 0..map.height {
-            let mut x = start_x;
-            while map.at(x, y) == 'O' && map.at(x + 1, y) == '.' {
-                *map.at_mut(x, y) = '.';
-                *map.at_mut(x + 1, y) = 'O';
-
-                if x < (map.width - 2) {
-                    x += 1;
-                }
-            }
+fn first_set_from(line: &[u64], start: usize, len: usize) -> usize {
+    let mut i = start / 64;
+    let mut word = line.get(i).map_or(0, |word| word & (!0 << (start % 64)));
+    loop {
+        if word != 0 {
+            return (i * 64 + word.trailing_zeros() as usize).min(len);
         }
+        i += 1;
+        if i >= line.len() {
+            return len;
+        }
+        word = line[i];
     }
 }
 
-fn slide_south(map: &mut Map) {
-    for start_y in (0..(map.height - 1)).rev() {
-        for x in 0..map.width {
-            let mut y = start_y;
-            while map.at(x, y) == 'O' && map.at(x, y + 1) == '.' {
-                *map.at_mut(x, y) = '.';
-                *map.at_mut(x, y + 1) = 'O';
+fn range_mask(word: usize, start: usize, end: usize) -> u64 {
+    let lo = start.saturating_sub(word * 64).min(64);
+    let hi = end.saturating_sub(word * 64).min(64);
+    if lo >= hi {
+        0
+    } else if hi - lo == 64 {
+        !0
+    } else {
+        ((1 << (hi - lo)) - 1) << lo
+    }
+}
 
-                if y < (map.height - 2) {
-                    y += 1;
-                }
+/// Slides round rocks to one end of each run of free space between cube rocks.
+fn slide_line(round: &mut [u64], cubes: &[u64], len: usize, towards_start: bool) {
+    let mut start = 0;
+    while start < len {
+        let end = first_set_from(cubes, start, len);
+
+        if end > start {
+            let words = start / 64..end.div_ceil(64);
+
+            let mut count = 0;
+            for i in words.clone() {
+                count += (round[i] & range_mask(i, start, end)).count_ones() as usize;
+            }
+
+            let (fill_start, fill_end) = if towards_start {
+                (start, start + count)
+            } else {
+                (end - count, end)
+            };
+
+            for i in words {
+                round[i] &= !range_mask(i, start, end);
+                round[i] |= range_mask(i, fill_start, fill_end);
             }
         }
+
+        start = end + 1;
     }
 }
 
-fn slide_west(map: &mut Map) {
-    for start_x in 1..map.width {
-        for y in 0..map.height {
-            let mut x = start_x;
-            while map.at(x, y) == 'O' && map.at(x - 1, y) == '.' {
-                *map.at_mut(x, y) = '.';
-                *map.at_mut(x - 1, y) = 'O';
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    North,
+    West,
+    South,
+    East,
+}
+
+/// Rock positions stored as bitsets of both rows and columns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Platform {
+    width: usize,
+    height: usize,
+    round_rows: Lines,
+    round_columns: Lines,
+    cube_rows: Lines,
+    cube_columns: Lines,
+}
+
+impl Platform {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let lines: Vec<_> = input.trim().lines().collect();
+        let height = lines.len();
+        let width = lines.first().map_or(0, |line| line.chars().count());
+        if width == 0 {
+            return Err(ParseError::Empty);
+        }
+
+        let mut round_rows = Lines::new(height, width);
+        let mut cube_rows = Lines::new(height, width);
+
+        for (y, line) in lines.iter().enumerate() {
+            let len = line.chars().count();
+            if len != width {
+                return Err(ParseError::RaggedRow { y, len, width });
+            }
 
-                if x > 1 {
-                    x -= 1;
+            for (x, tile) in line.chars().enumerate() {
+                match tile {
+                    'O' => round_rows.insert(y, x),
+                    '#' => cube_rows.insert(y, x),
+                    '.' => {}
+                    _ => return Err(ParseError::UnknownTile { x, y, tile }),
                 }
             }
         }
-    }
-}
 
-fn compute_load(map: &Map) -> u32 {
-    let mut total_load = 0;
-    for y in 0..map.height {
-        let distance = map.height - y;
+        let mut round_columns = Lines::new(width, height);
+        let mut cube_columns = Lines::new(width, height);
+        round_rows.transpose_into(&mut round_columns);
+        cube_rows.transpose_into(&mut cube_columns);
+
+        Ok(Self {
+            width,
+            height,
+            round_rows,
+            round_columns,
+            cube_rows,
+            cube_columns,
+        })
+    }
 
-        for x in 0..map.width {
-            if map.at(x, y) == 'O' {
-                total_load += distance as u32;
+    fn slide(&mut self, direction: Direction) {
+        match direction {
+            Direction::North | Direction::South => {
+                for x in 0..self.width {
+                    slide_line(
+                        self.round_columns.line_mut(x),
+                        self.cube_columns.line(x),
+                        self.height,
+                        direction == Direction::North,
+                    );
+                }
+                self.round_columns.transpose_into(&mut self.round_rows);
+            }
+            Direction::West | Direction::East => {
+                for y in 0..self.height {
+                    slide_line(
+                        self.round_rows.line_mut(y),
+                        self.cube_rows.line(y),
+                        self.width,
+                        direction == Direction::West,
+                    );
+                }
+                self.round_rows.transpose_into(&mut self.round_columns);
             }
         }
     }
-    total_load
-}
 
-pub fn part1(input: &str) -> String {
-    let mut map = Map::new(input);
+    pub fn spin_cycle(&mut self) {
+        self.slide(Direction::North);
+        self.slide(Direction::West);
+        self.slide(Direction::South);
+        self.slide(Direction::East);
+    }
 
-    // Slide all rocks up
-    slide_north(&mut map);
+    pub fn north_load(&self) -> u32 {
+        (0..self.height)
+            .map(|y| {
+                let rocks: u32 = self.round_rows.line(y).iter().map(|w| w.count_ones()).sum();
+                rocks * (self.height - y) as u32
+            })
+            .sum()
+    }
 
-    // North load
-    compute_load(&map).to_string()
-}
+    /// North load after `cycles` spin cycles, skipping ahead once the rocks start repeating.
+    pub fn load_after_cycles(&self, cycles: usize) -> u32 {
+        let mut platform = self.clone();
 
-pub fn part2(input: &str) -> String {
-    let mut map = Map::new(input);
+        let mut seen: HashMap<Vec<u64>, usize> = HashMap::new();
+        let mut loads = Vec::new();
 
-    let mut map_map: HashMap<Map, usize> = HashMap::new();
+        for i in 0..=cycles {
+            // The row bitsets alone identify the state, as cube rocks never move
+            if let Some(&cycle_start) = seen.get(&platform.round_rows.words) {
+                let cycle_len = i - cycle_start;
+                return loads[cycle_start + (cycles - cycle_start) % cycle_len];
+            }
 
-    for i in 0..1000000000 {
-        slide_north(&mut map);
-        slide_west(&mut map);
-        slide_south(&mut map);
-        slide_east(&mut map);
+            seen.insert(platform.round_rows.words.clone(), i);
+            loads.push(platform.north_load());
 
-        // Check if the current map state has been seen before
-        if let Some(cycle_start) = map_map.get(&map) {
-            // If a cycle is found, calculate the cycle length
-            let cycle_len = i - cycle_start;
+            platform.spin_cycle();
+        }
 
-            // Calculate the last iteration within the cycle
-            let last = (1000000000 - cycle_start) % cycle_len + cycle_start - 1;
+        loads[cycles]
+    }
+}
 
-            // Find the map state corresponding to the last iteration in the cycle
-            let map = map_map.iter().find(|(_, i)| **i == last).unwrap().0;
+pub fn part1(input: &str) -> String {
+    let mut platform = Platform::parse(input).expect("Invalid platform");
 
-            return compute_load(map).to_string();
-        }
+    // Slide all rocks up
+    platform.slide(Direction::North);
 
-        // If the current map state is new, add it to the HashMap
-        map_map.insert(map.clone(), i);
-    }
+    // North load
+    platform.north_load().to_string()
+}
 
-    unreachable!()
+pub fn part2(input: &str) -> String {
+    let platform = Platform::parse(input).expect("Invalid platform");
+    platform.load_after_cycles(1000000000).to_string()
 }
 
 #[cfg(test)]
@@ -168,6 +289,19 @@ O.#..O.#.#
 .......O..
 #....###..
 #OO..#....
+"#;
+
+    const AFTER_ONE_CYCLE: &str = r#"
+.....#....
+....#...O#
+...OO##...
+.OO#......
+.....OOO#.
+.O#...O#.#
+....O#....
+......OOOO
+#...O###..
+#..OO#....
 "#;
 
     #[test]
@@ -179,4 +313,36 @@ O.#..O.#.#
     fn test_part2() {
         assert_eq!(&part2(TEST_INPUT), "64");
     }
+
+    #[test]
+    fn test_spin_cycle() {
+        let mut platform = Platform::parse(TEST_INPUT).unwrap();
+        platform.spin_cycle();
+        assert_eq!(platform, Platform::parse(AFTER_ONE_CYCLE).unwrap());
+    }
+
+    #[test]
+    fn test_load_after_cycles() {
+        let platform = Platform::parse(TEST_INPUT).unwrap();
+        let mut expected = platform.clone();
+        for cycles in 0..20 {
+            assert_eq!(platform.load_after_cycles(cycles), expected.north_load());
+            expected.spin_cycle();
+        }
+    }
+
+    #[test]
+    fn test_wide_platform() {
+        // Rows spanning more than one word
+        let row = format!("{}O.#{}OO.", ".".repeat(68), ".".repeat(5));
+        let empty = format!("{}#{}", ".".repeat(70), ".".repeat(8));
+        let mut platform = Platform::parse(&format!("{}\n{}\n", row, empty)).unwrap();
+        platform.slide(Direction::West);
+        platform.slide(Direction::South);
+
+        let full = format!("O{}#OO{}", ".".repeat(69), ".".repeat(6));
+        let expected = Platform::parse(&format!("{}\n{}\n", empty, full)).unwrap();
+        assert_eq!(platform, expected);
+        assert_eq!(platform.north_load(), 3);
+    }
 }
@@ -1,28 +1,91 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, fmt};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    RaggedRow { y: usize, len: usize, width: usize },
+    UnknownTile { x: usize, y: usize, tile: char },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "map is empty"),
+            ParseError::RaggedRow { y, len, width } => {
+                write!(f, "row {} has length {}, expected {}", y, len, width)
+            }
+            ParseError::UnknownTile { x, y, tile } => {
+                write!(f, "unknown tile {:?} at ({}, {})", tile, x, y)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Tile {
+    Empty,
+    ForwardMirror,
+    BackMirror,
+    VerticalSplitter,
+    HorizontalSplitter,
+}
+
+impl Tile {
+    fn parse(ch: char) -> Option<Self> {
+        match ch {
+            '.' => Some(Tile::Empty),
+            '/' => Some(Tile::ForwardMirror),
+            '\\' => Some(Tile::BackMirror),
+            '|' => Some(Tile::VerticalSplitter),
+            '-' => Some(Tile::HorizontalSplitter),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct Map {
-    tiles: Vec<char>,
+    tiles: Vec<Tile>,
     width: usize,
     height: usize,
 }
 
 impl Map {
-    fn new(input: &str) -> Self {
-        let tiles: Vec<_> = input.trim().lines().flat_map(|line| line.chars()).collect();
+    fn parse(input: &str) -> Result<Self, ParseError> {
+        let mut tiles = Vec::new();
+        let mut width = 0;
+        let mut height = 0;
 
-        let width = input.trim().lines().next().unwrap().len();
-        let height = tiles.len() / width;
-        assert_eq!(width * height, tiles.len());
+        for (y, line) in input.trim().lines().enumerate() {
+            let len = line.chars().count();
+            if y == 0 {
+                width = len;
+            } else if len != width {
+                return Err(ParseError::RaggedRow { y, len, width });
+            }
 
-        Self {
+            for (x, ch) in line.chars().enumerate() {
+                let tile = Tile::parse(ch).ok_or(ParseError::UnknownTile { x, y, tile: ch })?;
+                tiles.push(tile);
+            }
+
+            height += 1;
+        }
+
+        if tiles.is_empty() {
+            return Err(ParseError::Empty);
+        }
+
+        Ok(Self {
             tiles,
             width,
             height,
-        }
+        })
     }
 
-    fn at(&self, x: usize, y: usize) -> char {
+    fn at(&self, x: usize, y: usize) -> Tile {
         let index = y * self.width + x;
         self.tiles[index]
     }
@@ -38,39 +101,38 @@ enum Direction {
 
 fn raytrace(map: &Map, x: isize, y: isize, direction: Direction) -> Vec<(isize, isize, Direction)> {
     match map.at(x as usize, y as usize) {
-        '.' => match direction {
+        Tile::Empty => match direction {
             Direction::North => vec![(x, y - 1, Direction::North)],
             Direction::East => vec![(x + 1, y, Direction::East)],
             Direction::South => vec![(x, y + 1, Direction::South)],
             Direction::West => vec![(x - 1, y, Direction::West)],
         },
-        '/' => match direction {
+        Tile::ForwardMirror => match direction {
             Direction::North => vec![(x + 1, y, Direction::East)],
             Direction::East => vec![(x, y - 1, Direction::North)],
             Direction::South => vec![(x - 1, y, Direction::West)],
             Direction::West => vec![(x, y + 1, Direction::South)],
         },
-        '\\' => match direction {
+        Tile::BackMirror => match direction {
             Direction::North => vec![(x - 1, y, Direction::West)],
             Direction::East => vec![(x, y + 1, Direction::South)],
             Direction::South => vec![(x + 1, y, Direction::East)],
             Direction::West => vec![(x, y - 1, Direction::North)],
         },
-        '|' => match direction {
+        Tile::VerticalSplitter => match direction {
             Direction::East | Direction::West => {
                 vec![(x, y - 1, Direction::North), (x, y + 1, Direction::South)]
             }
             Direction::North => vec![(x, y - 1, Direction::North)],
             Direction::South => vec![(x, y + 1, Direction::South)],
         },
-        '-' => match direction {
+        Tile::HorizontalSplitter => match direction {
             Direction::North | Direction::South => {
                 vec![(x - 1, y, Direction::West), (x + 1, y, Direction::East)]
             }
             Direction::East => vec![(x + 1, y, Direction::East)],
             Direction::West => vec![(x - 1, y, Direction::West)],
         },
-        _ => todo!(),
     }
 }
 
@@ -87,8 +149,8 @@ fn count_energised(map: &Map, start: (isize, isize, Direction)) -> usize {
             // If out of bounds
             if next_x < 0
                 || next_y < 0
-                || next_x as usize == map.width
-                || next_y as usize == map.height
+                || next_x as usize >= map.width
+                || next_y as usize >= map.height
             {
                 continue;
             }
@@ -104,33 +166,278 @@ fn count_energised(map: &Map, start: (isize, isize, Direction)) -> usize {
     energised_set.len()
 }
 
-pub fn part1(input: &str) -> String {
-    let map = Map::new(input);
-    count_energised(&map, (0, 0, Direction::East)).to_string()
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BitSet {
+    words: Vec<u64>,
 }
 
-pub fn part2(input: &str) -> String {
-    let map = Map::new(input);
+impl BitSet {
+    fn new(len: usize) -> Self {
+        Self {
+            words: vec![0; len.div_ceil(64)],
+        }
+    }
+
+    fn insert(&mut self, index: usize) {
+        self.words[index / 64] |= 1 << (index % 64);
+    }
+
+    fn union_with(&mut self, other: &BitSet) {
+        for (word, other) in self.words.iter_mut().zip(other.words.iter()) {
+            *word |= other;
+        }
+    }
+
+    fn union_len(&self, other: &BitSet) -> usize {
+        self.words
+            .iter()
+            .zip(other.words.iter())
+            .map(|(a, b)| (a | b).count_ones() as usize)
+            .sum()
+    }
+
+    fn len(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+}
+
+/// A straight run of beam between two splits, after following any mirrors.
+#[derive(Debug, Clone)]
+struct Segment {
+    tiles: BitSet,
+    /// Splitter the beam was split by, if it didn't leave the map or loop.
+    target: Option<usize>,
+}
+
+fn trace_segment(
+    map: &Map,
+    splitter_ids: &[Option<usize>],
+    start: (isize, isize, Direction),
+) -> Segment {
+    let mut tiles = BitSet::new(map.tiles.len());
+    let mut state = start;
+
+    loop {
+        let (x, y, direction) = state;
+        if x < 0 || y < 0 || x as usize >= map.width || y as usize >= map.height {
+            return Segment {
+                tiles,
+                target: None,
+            };
+        }
+
+        let index = y as usize * map.width + x as usize;
+        tiles.insert(index);
+
+        let next = raytrace(map, x, y, direction);
+        if next.len() > 1 {
+            return Segment {
+                tiles,
+                target: splitter_ids[index],
+            };
+        }
+        state = next[0];
+
+        // Unsplit moves are reversible, so a looping beam must return to where it began
+        if state == start {
+            return Segment {
+                tiles,
+                target: None,
+            };
+        }
+    }
+}
+
+/// Splitter graph of a map, with the tiles energised from each splitter precomputed.
+struct Beams<'a> {
+    map: &'a Map,
+    splitter_ids: Vec<Option<usize>>,
+    component_of: Vec<usize>,
+    /// Tiles energised once a beam has been split by any splitter in each component.
+    energised: Vec<BitSet>,
+}
+
+impl<'a> Beams<'a> {
+    fn new(map: &'a Map) -> Self {
+        let mut splitters = Vec::new();
+        let mut splitter_ids = vec![None; map.tiles.len()];
+        for (index, tile) in map.tiles.iter().enumerate() {
+            if matches!(tile, Tile::VerticalSplitter | Tile::HorizontalSplitter) {
+                splitter_ids[index] = Some(splitters.len());
+                splitters.push(index);
+            }
+        }
+
+        // Trace both halves of the beam leaving every splitter
+        let segments: Vec<[Segment; 2]> = splitters
+            .iter()
+            .map(|&index| {
+                let x = (index % map.width) as isize;
+                let y = (index / map.width) as isize;
+                let outputs = match map.at(x as usize, y as usize) {
+                    Tile::VerticalSplitter => {
+                        [(x, y - 1, Direction::North), (x, y + 1, Direction::South)]
+                    }
+                    _ => [(x - 1, y, Direction::West), (x + 1, y, Direction::East)],
+                };
+                outputs.map(|output| trace_segment(map, &splitter_ids, output))
+            })
+            .collect();
+
+        let components = strongly_connected_components(&segments);
+
+        // Components are emitted with successors first, so theirs are always ready
+        let mut component_of = vec![0; splitters.len()];
+        let mut component_energised = Vec::with_capacity(components.len());
+        for (component, members) in components.iter().enumerate() {
+            let mut tiles = BitSet::new(map.tiles.len());
+            for &node in members {
+                component_of[node] = component;
+                tiles.insert(splitters[node]);
+            }
+            for &node in members {
+                for segment in segments[node].iter() {
+                    tiles.union_with(&segment.tiles);
+                    if let Some(target) = segment.target {
+                        if !members.contains(&target) {
+                            tiles.union_with(&component_energised[component_of[target]]);
+                        }
+                    }
+                }
+            }
+            component_energised.push(tiles);
+        }
+
+        Self {
+            map,
+            splitter_ids,
+            component_of,
+            energised: component_energised,
+        }
+    }
+
+    fn count_energised(&self, start: (isize, isize, Direction)) -> usize {
+        let segment = trace_segment(self.map, &self.splitter_ids, start);
+        match segment.target {
+            Some(target) => segment
+                .tiles
+                .union_len(&self.energised[self.component_of[target]]),
+            None => segment.tiles.len(),
+        }
+    }
+}
+
+/// Tarjan's algorithm, returning components in reverse topological order.
+fn strongly_connected_components(segments: &[[Segment; 2]]) -> Vec<Vec<usize>> {
+    struct Tarjan<'a> {
+        segments: &'a [[Segment; 2]],
+        index: Vec<Option<usize>>,
+        low_link: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        next_index: usize,
+        components: Vec<Vec<usize>>,
+    }
+
+    impl Tarjan<'_> {
+        fn visit(&mut self, node: usize) {
+            self.index[node] = Some(self.next_index);
+            self.low_link[node] = self.next_index;
+            self.next_index += 1;
+            self.stack.push(node);
+            self.on_stack[node] = true;
+
+            for segment in self.segments[node].iter() {
+                let Some(target) = segment.target else {
+                    continue;
+                };
+                match self.index[target] {
+                    None => {
+                        self.visit(target);
+                        self.low_link[node] = self.low_link[node].min(self.low_link[target]);
+                    }
+                    Some(index) if self.on_stack[target] => {
+                        self.low_link[node] = self.low_link[node].min(index);
+                    }
+                    Some(_) => {}
+                }
+            }
 
-    let mut max_energised = 0;
+            if Some(self.low_link[node]) == self.index[node] {
+                let mut component = Vec::new();
+                loop {
+                    let member = self.stack.pop().unwrap();
+                    self.on_stack[member] = false;
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                self.components.push(component);
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        segments,
+        index: vec![None; segments.len()],
+        low_link: vec![0; segments.len()],
+        on_stack: vec![false; segments.len()],
+        stack: Vec::new(),
+        next_index: 0,
+        components: Vec::new(),
+    };
 
-    for x in 0..(map.width as isize) {
-        max_energised = max_energised.max(count_energised(&map, (x, 0, Direction::South)));
-        max_energised = max_energised.max(count_energised(
-            &map,
-            (x, map.height as isize - 1, Direction::North),
-        ));
+    for node in 0..segments.len() {
+        if tarjan.index[node].is_none() {
+            tarjan.visit(node);
+        }
     }
 
-    for y in 0..(map.width as isize) {
-        max_energised = max_energised.max(count_energised(&map, (0, y, Direction::East)));
-        max_energised = max_energised.max(count_energised(
-            &map,
-            (map.width as isize - 1, y, Direction::West),
-        ));
+    tarjan.components
+}
+
+fn edge_starts(map: &Map) -> Vec<(isize, isize, Direction)> {
+    let width = map.width as isize;
+    let height = map.height as isize;
+
+    let mut starts = Vec::with_capacity(2 * (map.width + map.height));
+    for x in 0..width {
+        starts.push((x, 0, Direction::South));
+        starts.push((x, height - 1, Direction::North));
+    }
+    for y in 0..height {
+        starts.push((0, y, Direction::East));
+        starts.push((width - 1, y, Direction::West));
     }
+    starts
+}
+
+pub fn part1(input: &str) -> String {
+    let map = Map::parse(input).expect("Invalid map");
+    count_energised(&map, (0, 0, Direction::East)).to_string()
+}
 
-    max_energised.to_string()
+pub fn part2(input: &str) -> String {
+    #[cfg(feature = "rayon")]
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+    let map = Map::parse(input).expect("Invalid map");
+    let beams = Beams::new(&map);
+
+    #[cfg(feature = "rayon")]
+    let starts = edge_starts(&map).into_par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let starts = edge_starts(&map).into_iter();
+
+    starts
+        .map(|start| beams.count_energised(start))
+        .max()
+        .unwrap()
+        .to_string()
 }
 
 #[cfg(test)]
@@ -148,6 +455,26 @@ mod tests {
 .-.-/..|..
 .|....-|.\
 ..//.|....
+"#;
+
+    const WIDE_INPUT: &str = r#"
+.....\
+..-...
+"#;
+
+    const TALL_INPUT: &str = r#"
+..
+.\
+..
+..
+|.
+"#;
+
+    const LOOP_INPUT: &str = r#"
+.-..\.
+..|...
+.\-./.
+.|.-.|
 "#;
 
     #[test]
@@ -159,4 +486,44 @@ mod tests {
     fn test_part2() {
         assert_eq!(&part2(TEST_INPUT), "51");
     }
+
+    #[test]
+    fn test_non_square() {
+        assert_eq!(&part1(WIDE_INPUT), "7");
+        assert_eq!(&part2(WIDE_INPUT), "7");
+        assert_eq!(&part1(TALL_INPUT), "2");
+        assert_eq!(&part2(TALL_INPUT), "6");
+    }
+
+    #[test]
+    fn test_beams_match_simulation() {
+        for input in [TEST_INPUT, WIDE_INPUT, TALL_INPUT, LOOP_INPUT] {
+            let map = Map::parse(input).unwrap();
+            let beams = Beams::new(&map);
+            for start in edge_starts(&map) {
+                assert_eq!(beams.count_energised(start), count_energised(&map, start));
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Map::parse("..\n.#\n"),
+            Err(ParseError::UnknownTile {
+                x: 1,
+                y: 1,
+                tile: '#'
+            })
+        );
+        assert_eq!(
+            Map::parse("...\n..\n"),
+            Err(ParseError::RaggedRow {
+                y: 1,
+                len: 2,
+                width: 3
+            })
+        );
+        assert_eq!(Map::parse("\n"), Err(ParseError::Empty));
+    }
 }
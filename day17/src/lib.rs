@@ -1,114 +1,484 @@
-use std::{
-    cmp::Ordering,
-    collections::{BinaryHeap, HashSet},
-};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    RaggedRow { y: usize, len: usize, width: usize },
+    UnknownTile { x: usize, y: usize, tile: char },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "map is empty"),
+            ParseError::RaggedRow { y, len, width } => {
+                write!(f, "row {} has length {}, expected {}", y, len, width)
+            }
+            ParseError::UnknownTile { x, y, tile } => {
+                write!(f, "unknown tile {:?} at ({}, {})", tile, x, y)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct Map {
+pub struct Map {
     tiles: Vec<u32>,
     width: usize,
     height: usize,
 }
 
 impl Map {
-    fn new(input: &str) -> Self {
-        let tiles: Vec<_> = input
-            .trim()
-            .lines()
-            .flat_map(|line| line.chars())
-            .map(|ch| ch.to_digit(10).unwrap())
-            .collect();
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let mut tiles = Vec::new();
+        let mut width = 0;
+        let mut height = 0;
 
-        let width = input.trim().lines().next().unwrap().len();
-        let height = tiles.len() / width;
-        assert_eq!(width * height, tiles.len());
+        for (y, line) in input.trim().lines().enumerate() {
+            let len = line.chars().count();
+            if y == 0 {
+                width = len;
+            } else if len != width {
+                return Err(ParseError::RaggedRow { y, len, width });
+            }
 
-        Self {
+            for (x, ch) in line.chars().enumerate() {
+                let tile = ch
+                    .to_digit(10)
+                    .ok_or(ParseError::UnknownTile { x, y, tile: ch })?;
+                tiles.push(tile);
+            }
+
+            height += 1;
+        }
+
+        if tiles.is_empty() {
+            return Err(ParseError::Empty);
+        }
+
+        Ok(Self {
             tiles,
             width,
             height,
+        })
+    }
+
+    /// Index of the neighbouring block in `direction`, if there is one.
+    fn step(&self, pos: usize, direction: Direction) -> Option<usize> {
+        match direction {
+            Direction::Up => pos.checked_sub(self.width),
+            Direction::Right => (!(pos + 1).is_multiple_of(self.width)).then_some(pos + 1),
+            Direction::Down => Some(pos + self.width).filter(|&next| next < self.tiles.len()),
+            Direction::Left => (!pos.is_multiple_of(self.width)).then(|| pos - 1),
         }
     }
 
-    fn get(&self, x: isize, y: isize) -> Option<u32> {
-        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
-            return None;
+    /// Draws the map with the route marked by arrows, as in the puzzle text.
+    pub fn render(&self, route: &Route) -> String {
+        let mut chars: Vec<_> = self
+            .tiles
+            .iter()
+            .map(|&tile| char::from_digit(tile, 10).unwrap())
+            .collect();
+
+        for step in route.steps.iter() {
+            let [x, y] = step.position;
+            chars[y * self.width + x] = step.direction.arrow();
         }
-        let index = y as usize * self.width + x as usize;
-        Some(self.tiles[index])
+
+        let mut output = String::with_capacity((self.width + 1) * self.height);
+        for row in chars.chunks(self.width) {
+            output.extend(row);
+            output.push('\n');
+        }
+        output
     }
 }
 
-#[derive(Clone, PartialEq, Eq)]
-struct State {
-    cost: u32,
-    pos: [isize; 2],
-    dir: [isize; 2],
-    dist: usize,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Up,
+    Right,
+    Down,
+    Left,
 }
 
-impl Ord for State {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        other.cost.cmp(&self.cost)
+impl Direction {
+    /// In clockwise order, so turning is adding one or three.
+    const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Right,
+        Direction::Down,
+        Direction::Left,
+    ];
+
+    pub fn arrow(&self) -> char {
+        match self {
+            Direction::Up => '^',
+            Direction::Down => 'v',
+            Direction::Left => '<',
+            Direction::Right => '>',
+        }
     }
 }
 
-impl PartialOrd for State {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
+/// A block entered by the crucible, and the direction it was moving in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    pub position: [usize; 2],
+    pub direction: Direction,
 }
 
-fn dijkstra(map: &Map, min: usize, max: usize) -> u32 {
-    // Construct heap with initial state
-    let mut heap = BinaryHeap::from_iter([[0, 1], [1, 0]].map(|velocity| State {
-        cost: 0,
-        pos: [0, 0],
-        dir: velocity,
-        dist: 0,
-    }));
+/// The blocks a crucible moves through after leaving the start, in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub heat_loss: u32,
+    pub steps: Vec<Step>,
+}
 
-    let mut visited = HashSet::new();
+/// Priority queue for small integer priorities, which must never be lower than the last
+/// priority popped.
+struct BucketQueue {
+    buckets: Vec<Vec<u32>>,
+    current: usize,
+}
 
-    while let Some(State {
-        cost,
-        pos: [x, y],
-        dir: [dx, dy],
-        dist,
-    }) = heap.pop()
-    {
-        if x as usize == map.width - 1 && y as usize == map.height - 1 && dist >= min {
-            return cost;
+impl BucketQueue {
+    fn new() -> Self {
+        Self {
+            buckets: Vec::new(),
+            current: 0,
         }
+    }
 
-        if !visited.insert((x, y, dx, dy, dist)) {
+    fn push(&mut self, priority: usize, item: u32) {
+        if priority >= self.buckets.len() {
+            self.buckets.resize_with(priority + 1, Vec::new);
+        }
+        self.buckets[priority].push(item);
+    }
+
+    fn pop(&mut self) -> Option<(usize, u32)> {
+        while let Some(bucket) = self.buckets.get_mut(self.current) {
+            if let Some(item) = bucket.pop() {
+                return Some((self.current, item));
+            }
+            self.current += 1;
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Search {
+    Dijkstra,
+    /// Guided by the heat loss to the goal when the crucible can turn freely.
+    AStar,
+}
+
+/// Least heat loss from every block to `goal`, ignoring how far the crucible must move
+/// between turns.
+fn heat_loss_to(map: &Map, goal: usize) -> Vec<u32> {
+    let mut heat_loss = vec![u32::MAX; map.tiles.len()];
+    let mut queue = BucketQueue::new();
+
+    heat_loss[goal] = 0;
+    queue.push(0, goal as u32);
+
+    while let Some((cost, pos)) = queue.pop() {
+        let pos = pos as usize;
+        if heat_loss[pos] as usize != cost {
             continue;
         }
 
-        let moves = [[-dy, dx], [dy, -dx], [dx, dy]];
-        for &[dx2, dy2] in &moves[2 * usize::from(dist < min)..3 - usize::from(dist >= max)] {
-            if let Some(move_cost) = map.get(x + dx2, y + dy2) {
-                heap.push(State {
-                    cost: cost + move_cost,
-                    pos: [x + dx2, y + dy2],
-                    dir: [dx2, dy2],
-                    dist: usize::from([dx, dy] == [dx2, dy2]) * dist + 1,
-                });
+        // Moving from next to pos loses the heat of pos
+        let cost = heat_loss[pos] + map.tiles[pos];
+        for direction in Direction::ALL {
+            if let Some(next) = map.step(pos, direction) {
+                if cost < heat_loss[next] {
+                    heat_loss[next] = cost;
+                    queue.push(cost as usize, next as u32);
+                }
+            }
+        }
+    }
+
+    heat_loss
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SolveError {
+    OutsideMap {
+        x: usize,
+        y: usize,
+    },
+    /// There are too many states to number them all with a `u32`.
+    TooLarge,
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolveError::OutsideMap { x, y } => write!(f, "({}, {}) is outside the map", x, y),
+            SolveError::TooLarge => write!(f, "map is too large to search"),
+        }
+    }
+}
+
+impl std::error::Error for SolveError {}
+
+/// How a crucible is allowed to move, and where it goes from and to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Solver {
+    /// Blocks the crucible must move in a straight line before it can turn or stop.
+    pub min_run: usize,
+    /// Blocks the crucible can move in a straight line before it must turn.
+    pub max_run: usize,
+    /// Whether the crucible can turn around as well as left and right.
+    pub reversing: bool,
+    pub start: [usize; 2],
+    /// The bottom-right block if not given.
+    pub goal: Option<[usize; 2]>,
+    pub search: Search,
+}
+
+impl Solver {
+    pub fn new(min_run: usize, max_run: usize) -> Self {
+        Self {
+            min_run,
+            max_run,
+            reversing: false,
+            start: [0, 0],
+            goal: None,
+            search: Search::AStar,
+        }
+    }
+
+    /// Finds the route with the least heat loss from the start to the goal, if there is
+    /// one.
+    pub fn solve(&self, map: &Map) -> Result<Option<Route>, SolveError> {
+        let index_of = |[x, y]: [usize; 2]| {
+            if x < map.width && y < map.height {
+                Ok(y * map.width + x)
+            } else {
+                Err(SolveError::OutsideMap { x, y })
+            }
+        };
+        let start = index_of(self.start)?;
+        let goal = index_of(self.goal.unwrap_or([map.width - 1, map.height - 1]))?;
+
+        // A run can't be longer than the map, so there's no need for states beyond that
+        let min = self.min_run;
+        let max = self.max_run.min(map.width.max(map.height));
+
+        if start == goal {
+            return Ok(Some(Route {
+                heat_loss: 0,
+                steps: Vec::new(),
+            }));
+        }
+        if max == 0 {
+            return Ok(None);
+        }
+
+        let heuristic = match self.search {
+            Search::Dijkstra => vec![0; map.tiles.len()],
+            Search::AStar => heat_loss_to(map, goal),
+        };
+
+        // States are indexed by position, direction and the length of the current run
+        let index =
+            |pos: usize, direction: usize, run: usize| (pos * 4 + direction) * max + run - 1;
+        // States are stored as u32, with u32::MAX marking the lack of a previous state
+        let states = map
+            .tiles
+            .len()
+            .checked_mul(4 * max)
+            .filter(|&states| states < u32::MAX as usize)
+            .ok_or(SolveError::TooLarge)?;
+
+        let mut costs = vec![u32::MAX; states];
+        let mut previous = vec![u32::MAX; states];
+        let mut queue = BucketQueue::new();
+
+        for (direction, &towards) in Direction::ALL.iter().enumerate() {
+            if let Some(next) = map.step(start, towards) {
+                if heuristic[next] == u32::MAX {
+                    continue;
+                }
+                let state = index(next, direction, 1);
+                costs[state] = map.tiles[next];
+                queue.push((costs[state] + heuristic[next]) as usize, state as u32);
+            }
+        }
+
+        let turns: &[usize] = if self.reversing { &[1, 2, 3] } else { &[1, 3] };
+
+        while let Some((priority, state)) = queue.pop() {
+            let state = state as usize;
+            let pos = state / (4 * max);
+            let direction = state / max % 4;
+            let run = state % max + 1;
+
+            let cost = costs[state];
+            if (cost + heuristic[pos]) as usize != priority {
+                continue;
+            }
+
+            if pos == goal && run >= min {
+                return Ok(Some(Route {
+                    heat_loss: cost,
+                    steps: trace_route(map, &previous, state, max),
+                }));
+            }
+
+            let straight = (run < max).then_some((direction, run + 1));
+            let turns = turns
+                .iter()
+                .filter(|_| run >= min)
+                .map(|turn| ((direction + turn) % 4, 1));
+
+            for (direction, run) in straight.into_iter().chain(turns) {
+                let Some(next) = map.step(pos, Direction::ALL[direction]) else {
+                    continue;
+                };
+                let next_state = index(next, direction, run);
+                let next_cost = cost + map.tiles[next];
+                if next_cost < costs[next_state] {
+                    costs[next_state] = next_cost;
+                    previous[next_state] = state as u32;
+                    queue.push((next_cost + heuristic[next]) as usize, next_state as u32);
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+fn trace_route(map: &Map, previous: &[u32], last: usize, max: usize) -> Vec<Step> {
+    let mut steps = Vec::new();
+    let mut state = last as u32;
+    while state != u32::MAX {
+        let pos = state as usize / (4 * max);
+        steps.push(Step {
+            position: [pos % map.width, pos / map.width],
+            direction: Direction::ALL[state as usize / max % 4],
+        });
+        state = previous[state as usize];
+    }
+    steps.reverse();
+    steps
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionError {
+    Unknown(String),
+    InvalidValue { key: String, value: String },
+    InvalidMap(ParseError),
+    Solve(SolveError),
+}
+
+impl fmt::Display for OptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptionError::Unknown(key) => write!(f, "unknown option {:?}", key),
+            OptionError::InvalidValue { key, value } => {
+                write!(f, "invalid value {:?} for option {:?}", value, key)
+            }
+            OptionError::InvalidMap(error) => write!(f, "invalid map: {}", error),
+            OptionError::Solve(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for OptionError {}
+
+impl From<SolveError> for OptionError {
+    fn from(error: SolveError) -> Self {
+        OptionError::Solve(error)
+    }
+}
+
+fn parse_position(value: &str) -> Option<[usize; 2]> {
+    let (x, y) = value.split_once(',')?;
+    Some([x.trim().parse().ok()?, y.trim().parse().ok()?])
+}
+
+/// Solves with `solver` changed by the options `min`, `max`, `reversing`, `start` and
+/// `goal` (as `x,y`), and `search` (`astar` or `dijkstra`), drawing the route as well if
+/// `render` is set.
+fn solve_with_options(
+    input: &str,
+    mut solver: Solver,
+    options: &[(&str, &str)],
+) -> Result<String, OptionError> {
+    let mut render = false;
+
+    for &(key, value) in options {
+        let invalid = || OptionError::InvalidValue {
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+        match key {
+            "min" => solver.min_run = value.parse().map_err(|_| invalid())?,
+            "max" => solver.max_run = value.parse().map_err(|_| invalid())?,
+            "reversing" => solver.reversing = value.parse().map_err(|_| invalid())?,
+            "start" => solver.start = parse_position(value).ok_or_else(invalid)?,
+            "goal" => solver.goal = Some(parse_position(value).ok_or_else(invalid)?),
+            "search" => {
+                solver.search = match value {
+                    "astar" => Search::AStar,
+                    "dijkstra" => Search::Dijkstra,
+                    _ => return Err(invalid()),
+                }
             }
+            "render" => render = value.parse().map_err(|_| invalid())?,
+            _ => return Err(OptionError::Unknown(key.to_string())),
         }
     }
 
-    unreachable!()
+    let map = Map::parse(input).map_err(OptionError::InvalidMap)?;
+    let Some(route) = solver.solve(&map)? else {
+        return Ok("No route".to_string());
+    };
+
+    let mut output = route.heat_loss.to_string();
+    if render {
+        output.push('\n');
+        output += map.render(&route).trim_end();
+    }
+    Ok(output)
 }
 
 pub fn part1(input: &str) -> String {
-    let map = Map::new(input);
-    dijkstra(&map, 0, 3).to_string()
+    let map = Map::parse(input).expect("Invalid map");
+    Solver::new(1, 3)
+        .solve(&map)
+        .expect("Can't search map")
+        .expect("No route")
+        .heat_loss
+        .to_string()
+}
+
+pub fn part1_with_options(input: &str, options: &[(&str, &str)]) -> Result<String, OptionError> {
+    solve_with_options(input, Solver::new(1, 3), options)
 }
 
 pub fn part2(input: &str) -> String {
-    let map = Map::new(input);
-    dijkstra(&map, 4, 10).to_string()
+    let map = Map::parse(input).expect("Invalid map");
+    Solver::new(4, 10)
+        .solve(&map)
+        .expect("Can't search map")
+        .expect("No route")
+        .heat_loss
+        .to_string()
+}
+
+pub fn part2_with_options(input: &str, options: &[(&str, &str)]) -> Result<String, OptionError> {
+    solve_with_options(input, Solver::new(4, 10), options)
 }
 
 #[cfg(test)]
@@ -131,6 +501,57 @@ mod tests {
 4322674655533
 "#;
 
+    const UNLUCKY_INPUT: &str = r#"
+111111111111
+999999999991
+999999999991
+999999999991
+999999999991
+"#;
+
+    /// Checks the route is connected, follows the rules of the solver, and loses the heat
+    /// it claims to.
+    fn check_route(map: &Map, route: &Route, solver: &Solver) {
+        let mut position = solver.start;
+        let mut run = 0;
+        let mut heat_loss = 0;
+        let mut previous = None;
+
+        for step in route.steps.iter() {
+            let [x, y] = position;
+            let expected = match step.direction {
+                Direction::Up => [x, y - 1],
+                Direction::Down => [x, y + 1],
+                Direction::Left => [x - 1, y],
+                Direction::Right => [x + 1, y],
+            };
+            assert_eq!(step.position, expected);
+
+            if previous == Some(step.direction) {
+                run += 1;
+            } else {
+                if let Some(previous) = previous {
+                    assert!(run >= solver.min_run);
+                    let reverse = Direction::ALL[(previous as usize + 2) % 4];
+                    assert!(solver.reversing || step.direction != reverse);
+                }
+                run = 1;
+            }
+            assert!(run <= solver.max_run);
+
+            previous = Some(step.direction);
+            position = step.position;
+            heat_loss += map.tiles[position[1] * map.width + position[0]];
+        }
+
+        assert!(route.steps.is_empty() || run >= solver.min_run);
+        assert_eq!(
+            position,
+            solver.goal.unwrap_or([map.width - 1, map.height - 1])
+        );
+        assert_eq!(heat_loss, route.heat_loss);
+    }
+
     #[test]
     fn test_part1() {
         assert_eq!(&part1(TEST_INPUT), "102");
@@ -140,4 +561,135 @@ mod tests {
     fn test_part2() {
         assert_eq!(&part2(TEST_INPUT), "94");
     }
+
+    #[test]
+    fn test_unlucky() {
+        assert_eq!(&part2(UNLUCKY_INPUT), "71");
+    }
+
+    #[test]
+    fn test_route() {
+        let reversing = Solver {
+            reversing: true,
+            ..Solver::new(1, 3)
+        };
+        let backwards = Solver {
+            start: [12, 12],
+            goal: Some([0, 0]),
+            ..Solver::new(4, 10)
+        };
+        let middle = Solver {
+            start: [6, 2],
+            goal: Some([3, 10]),
+            ..Solver::new(2, 5)
+        };
+
+        for (input, solver) in [
+            (TEST_INPUT, Solver::new(1, 3)),
+            (TEST_INPUT, Solver::new(4, 10)),
+            (UNLUCKY_INPUT, Solver::new(4, 10)),
+            (TEST_INPUT, reversing),
+            (TEST_INPUT, backwards),
+            (TEST_INPUT, middle),
+        ] {
+            let map = Map::parse(input).unwrap();
+            let route = solver.solve(&map).unwrap().unwrap();
+            check_route(&map, &route, &solver);
+
+            let dijkstra = Solver {
+                search: Search::Dijkstra,
+                ..solver
+            };
+            assert_eq!(
+                dijkstra.solve(&map).unwrap().unwrap().heat_loss,
+                route.heat_loss
+            );
+        }
+    }
+
+    #[test]
+    fn test_reversing() {
+        // Stopping at the goal needs a longer run than there is room for, unless the
+        // crucible goes past the start and comes back
+        let map = Map::parse("1119").unwrap();
+        let solver = Solver {
+            start: [1, 0],
+            goal: Some([0, 0]),
+            ..Solver::new(2, 3)
+        };
+        assert_eq!(solver.solve(&map), Ok(None));
+
+        let reversing = Solver {
+            reversing: true,
+            ..solver
+        };
+        let route = reversing.solve(&map).unwrap().unwrap();
+        check_route(&map, &route, &reversing);
+        assert_eq!(route.heat_loss, 13);
+    }
+
+    #[test]
+    fn test_options() {
+        assert_eq!(part1_with_options(TEST_INPUT, &[]), Ok("102".to_string()));
+        assert_eq!(
+            part2_with_options(TEST_INPUT, &[("min", "1"), ("max", "3")]),
+            Ok("102".to_string())
+        );
+        assert_eq!(
+            part1_with_options("19\n11", &[("goal", "1,0"), ("render", "true")]),
+            Ok("9\n1>\n11".to_string())
+        );
+        assert_eq!(
+            part1_with_options(TEST_INPUT, &[("goal", "13,0")]),
+            Err(OptionError::Solve(SolveError::OutsideMap { x: 13, y: 0 }))
+        );
+        assert_eq!(
+            part1_with_options(TEST_INPUT, &[("max", "100000000")]),
+            part1_with_options(TEST_INPUT, &[("max", "13")])
+        );
+        assert_eq!(
+            part1_with_options("12\n3x", &[]),
+            Err(OptionError::InvalidMap(ParseError::UnknownTile {
+                x: 1,
+                y: 1,
+                tile: 'x'
+            }))
+        );
+        assert_eq!(
+            part1_with_options(TEST_INPUT, &[("start", "1")]),
+            Err(OptionError::InvalidValue {
+                key: "start".to_string(),
+                value: "1".to_string()
+            })
+        );
+        assert_eq!(
+            part1_with_options(TEST_INPUT, &[("size", "1")]),
+            Err(OptionError::Unknown("size".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let map = Map::parse(UNLUCKY_INPUT).unwrap();
+
+        let steps = (1..8)
+            .map(|x| ([x, 0], Direction::Right))
+            .chain((1..5).map(|y| ([7, y], Direction::Down)))
+            .chain((8..12).map(|x| ([x, 4], Direction::Right)))
+            .map(|(position, direction)| Step {
+                position,
+                direction,
+            })
+            .collect();
+
+        let route = Route {
+            heat_loss: 71,
+            steps,
+        };
+        check_route(&map, &route, &Solver::new(4, 10));
+        assert_eq!(
+            map.render(&route),
+            "1>>>>>>>1111\n9999999v9991\n9999999v9991\n9999999v9991\n9999999v>>>>\n"
+        );
+    }
 }
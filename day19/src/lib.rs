@@ -1,21 +1,93 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    MissingParts,
+    InvalidWorkflow(String),
+    InvalidRule(String),
+    InvalidPart(String),
+    MissingRating {
+        part: String,
+        category: String,
+    },
+    DuplicateWorkflow(String),
+    UnknownWorkflow(String),
+    MissingStart,
+    /// Parts sent to this workflow can end up back in it.
+    Cycle(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::MissingParts => write!(f, "expected parts after a blank line"),
+            ParseError::InvalidWorkflow(line) => write!(f, "invalid workflow: {:?}", line),
+            ParseError::InvalidRule(rule) => write!(f, "invalid rule: {:?}", rule),
+            ParseError::InvalidPart(line) => write!(f, "invalid part: {:?}", line),
+            ParseError::MissingRating { part, category } => {
+                write!(f, "part {:?} has no {} rating", part, category)
+            }
+            ParseError::DuplicateWorkflow(name) => write!(f, "workflow {} defined twice", name),
+            ParseError::UnknownWorkflow(name) => write!(f, "unknown workflow {}", name),
+            ParseError::MissingStart => write!(f, "missing workflow in"),
+            ParseError::Cycle(name) => write!(f, "workflow {} is part of a cycle", name),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Ranges were given for a different number of categories than the workflows check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrongRangeCount {
+    pub expected: usize,
+    pub found: usize,
+}
+
+impl fmt::Display for WrongRangeCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "expected {} ranges, one per category, found {}",
+            self.expected, self.found
+        )
+    }
+}
+
+impl std::error::Error for WrongRangeCount {}
 
 #[derive(Debug)]
 struct Part {
-    categories: [usize; 4],
+    /// Ratings in the order of the categories of the workflows.
+    ratings: Vec<usize>,
+    /// Sum of all ratings, including categories that no workflow checks.
+    total: usize,
 }
 
-impl Part {
-    fn parse(input: &str) -> Part {
-        let categories = array_init::from_iter(
-            input[1..input.len() - 1]
-                .split(',')
-                .map(|cat| cat.split_once('=').unwrap().1.parse::<usize>().unwrap()),
-        )
-        .unwrap();
+/// Parses the named ratings of a part.
+fn parse_ratings(input: &str) -> Result<Vec<(&str, usize)>, ParseError> {
+    let invalid = || ParseError::InvalidPart(input.to_string());
+
+    let ratings = input
+        .strip_prefix('{')
+        .and_then(|input| input.strip_suffix('}'))
+        .ok_or_else(invalid)?;
+
+    let mut parsed: Vec<(&str, usize)> = Vec::new();
+    for rating in ratings.split(',') {
+        let (name, rating) = rating.split_once('=').ok_or_else(invalid)?;
+        let rating = rating.parse().map_err(|_| invalid())?;
 
-        Part { categories }
+        if parsed.iter().any(|&(seen, _)| seen == name) {
+            return Err(invalid());
+        }
+        parsed.push((name, rating));
     }
+
+    Ok(parsed)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -35,189 +107,488 @@ impl<'a> Destination<'a> {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Condition {
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    Always,
+}
+
+impl Condition {
+    /// Inclusive range of ratings that match, if any do.
+    fn interval(&self, rhs: usize) -> Option<[usize; 2]> {
+        match self {
+            Condition::Less => Some([0, rhs.checked_sub(1)?]),
+            Condition::LessEqual => Some([0, rhs]),
+            Condition::Greater => Some([rhs.checked_add(1)?, usize::MAX]),
+            Condition::GreaterEqual => Some([rhs, usize::MAX]),
+            Condition::Equal => Some([rhs, rhs]),
+            Condition::Always => Some([0, usize::MAX]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 struct Rule<'a> {
-    category: usize,
-    condition: char,
+    category: &'a str,
+    condition: Condition,
     rhs: usize,
     destination: Destination<'a>,
 }
 
 impl<'a> Rule<'a> {
-    fn parse(input: &'a str) -> Rule<'a> {
-        let category = match input.chars().nth(0).unwrap() {
-            'x' => 0,
-            'm' => 1,
-            'a' => 2,
-            's' => 3,
-            _ => unreachable!(),
+    fn parse(input: &'a str) -> Result<Rule<'a>, ParseError> {
+        let invalid = || ParseError::InvalidRule(input.to_string());
+
+        let Some((condition, destination)) = input.split_once(':') else {
+            // Final rule of a workflow
+            return Ok(Rule {
+                category: "",
+                condition: Condition::Always,
+                rhs: 0,
+                destination: Destination::parse(input),
+            });
         };
-        let condition = input.chars().nth(1).unwrap();
 
-        let (rhs, destination) = input[2..].split_once(':').unwrap();
+        let split = condition.find(['<', '>', '=']).ok_or_else(invalid)?;
+        let (category, rhs) = condition.split_at(split);
+        if category.is_empty() {
+            return Err(invalid());
+        }
 
-        let rhs = rhs.parse::<usize>().unwrap();
-        let destination = Destination::parse(destination);
+        let (condition, rhs) = [
+            ("<=", Condition::LessEqual),
+            (">=", Condition::GreaterEqual),
+            ("==", Condition::Equal),
+            ("<", Condition::Less),
+            (">", Condition::Greater),
+        ]
+        .into_iter()
+        .find_map(|(operator, condition)| Some((condition, rhs.strip_prefix(operator)?)))
+        .ok_or_else(invalid)?;
+        let rhs = rhs.parse::<usize>().map_err(|_| invalid())?;
 
-        Rule {
+        Ok(Rule {
             category,
             condition,
             rhs,
-            destination,
-        }
+            destination: Destination::parse(destination),
+        })
     }
+}
 
-    fn matches(&self, part: &Part) -> Option<Destination> {
-        if match self.condition {
-            '<' => part.categories[self.category] < self.rhs,
-            '>' => part.categories[self.category] > self.rhs,
-            _ => unreachable!(),
-        } {
-            Some(self.destination)
-        } else {
-            None
-        }
+fn parse_workflow(line: &str) -> Result<(&str, Vec<Rule<'_>>), ParseError> {
+    let invalid = || ParseError::InvalidWorkflow(line.to_string());
+
+    let (name, rules) = line.split_once('{').ok_or_else(invalid)?;
+    let rules = rules.strip_suffix('}').ok_or_else(invalid)?;
+
+    let rules = rules
+        .split(',')
+        .map(Rule::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Only the last rule may be unconditional, and there must be one
+    let (last, rest) = rules.split_last().ok_or_else(invalid)?;
+    if last.condition != Condition::Always
+        || rest.iter().any(|rule| rule.condition == Condition::Always)
+    {
+        return Err(invalid());
     }
-}
 
-#[derive(Debug)]
-struct Workflow<'a> {
-    rules: Vec<Rule<'a>>,
-    otherwise: Destination<'a>,
+    Ok((name, rules))
 }
 
-impl<'a> Workflow<'a> {
-    fn parse(line: &'a str) -> (&'a str, Workflow<'a>) {
-        let mut parts = line.split(['{', '}']);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    /// Index of the first instruction of another workflow.
+    Jump(usize),
+    Rejected,
+    Accepted,
+}
 
-        let name = parts.next().unwrap();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Instruction {
+    category: usize,
+    condition: Condition,
+    rhs: usize,
+    target: Target,
+}
 
-        let rules = parts.next().unwrap();
-        let rules = || rules.split(',');
+impl Instruction {
+    fn matches(&self, part: &Part) -> bool {
+        if self.condition == Condition::Always {
+            return true;
+        }
+        let rating = part.ratings[self.category];
+        self.condition
+            .interval(self.rhs)
+            .is_some_and(|[min, max]| min <= rating && rating <= max)
+    }
+}
 
-        let otherwise = rules().last().map(Destination::parse).unwrap();
-        let rules: Vec<_> = rules().take(rules().count() - 1).map(Rule::parse).collect();
+/// A box of ratings that is accepted, along with the workflows that sort it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region<'a> {
+    /// Inclusive range of ratings, in the order of [`Workflows::categories`].
+    pub ranges: Vec<[usize; 2]>,
+    pub path: Vec<&'a str>,
+}
 
-        (name, Workflow { rules, otherwise })
+impl Region<'_> {
+    pub fn combinations(&self) -> u128 {
+        self.ranges
+            .iter()
+            .map(|range| (range[1] - range[0]) as u128 + 1)
+            .product()
     }
+}
 
-    fn sort(&self, part: &Part) -> Destination {
-        for rule in self.rules.iter() {
-            if let Some(destination) = rule.matches(part) {
-                return destination;
+/// Workflows compiled into one flat list of instructions, with every destination resolved
+/// to the index of the first instruction of its workflow.
+#[derive(Debug, Clone)]
+pub struct Workflows {
+    code: Vec<Instruction>,
+    start: usize,
+    names: HashMap<usize, String>,
+    categories: Vec<String>,
+}
+
+impl Workflows {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let workflows = input
+            .lines()
+            .map(parse_workflow)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Lay the workflows out one after another
+        let mut entries = HashMap::new();
+        let mut offset = 0;
+        for (name, rules) in workflows.iter() {
+            if entries.insert(*name, offset).is_some() {
+                return Err(ParseError::DuplicateWorkflow(name.to_string()));
             }
+            offset += rules.len();
+        }
+
+        // Categories are numbered in sorted order, whatever order the rules check them in
+        let categories: BTreeSet<&str> = workflows
+            .iter()
+            .flat_map(|(_, rules)| rules)
+            .filter(|rule| rule.condition != Condition::Always)
+            .map(|rule| rule.category)
+            .collect();
+        let category_indices: HashMap<&str, usize> = categories
+            .iter()
+            .enumerate()
+            .map(|(index, &category)| (category, index))
+            .collect();
+
+        let mut code = Vec::with_capacity(offset);
+        for rule in workflows.iter().flat_map(|(_, rules)| rules) {
+            let target = match rule.destination {
+                Destination::Workflow(name) => Target::Jump(
+                    *entries
+                        .get(name)
+                        .ok_or_else(|| ParseError::UnknownWorkflow(name.to_string()))?,
+                ),
+                Destination::Rejected => Target::Rejected,
+                Destination::Accepted => Target::Accepted,
+            };
+            let category = match rule.condition {
+                Condition::Always => 0,
+                _ => category_indices[rule.category],
+            };
+            code.push(Instruction {
+                category,
+                condition: rule.condition,
+                rhs: rule.rhs,
+                target,
+            });
+        }
+
+        let start = *entries.get("in").ok_or(ParseError::MissingStart)?;
+        let names = entries
+            .into_iter()
+            .map(|(name, pc)| (pc, name.to_string()))
+            .collect();
+        let compiled = Self {
+            code,
+            start,
+            names,
+            categories: categories.into_iter().map(str::to_string).collect(),
+        };
+
+        // Sorting would never finish if a workflow could be revisited
+        if let Some(entry) = compiled.find_cycle() {
+            return Err(ParseError::Cycle(compiled.names[&entry].clone()));
         }
-        self.otherwise
+
+        Ok(compiled)
     }
-}
 
-pub fn part1(input: &str) -> String {
-    // Parse input
-    let (workflows, parts) = input.trim().split_once("\n\n").unwrap();
-    let workflows: HashMap<_, _> = workflows.lines().map(Workflow::parse).collect();
-    let parts: Vec<_> = parts.lines().map(Part::parse).collect();
+    /// Names of the categories checked by the workflows, sorted, which is the order ranges
+    /// are given in.
+    pub fn categories(&self) -> &[String] {
+        &self.categories
+    }
 
-    let mut accepted_parts = Vec::new();
+    /// Entries of the workflows in the order they were laid out.
+    fn entries(&self) -> impl Iterator<Item = usize> + '_ {
+        std::iter::once(0).chain(
+            self.code
+                .iter()
+                .enumerate()
+                .filter(|(_, instruction)| instruction.condition == Condition::Always)
+                .map(|(pc, _)| pc + 1)
+                .filter(|&pc| pc < self.code.len()),
+        )
+    }
 
-    for part in parts {
-        let mut current_workflow = workflows.get("in").unwrap();
+    /// Entry of a workflow that can jump back to itself, if there is one.
+    fn find_cycle(&self) -> Option<usize> {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        enum Visit {
+            New,
+            InProgress,
+            Done,
+        }
 
-        loop {
-            match current_workflow.sort(&part) {
-                Destination::Workflow(name) => current_workflow = workflows.get(name).unwrap(),
-                Destination::Rejected => break,
-                Destination::Accepted => {
-                    accepted_parts.push(part);
+        fn visit(workflows: &Workflows, entry: usize, visits: &mut [Visit]) -> Option<usize> {
+            match visits[entry] {
+                Visit::New => visits[entry] = Visit::InProgress,
+                Visit::InProgress => return Some(entry),
+                Visit::Done => return None,
+            }
+
+            for instruction in workflows.code[entry..].iter() {
+                if let Target::Jump(next) = instruction.target {
+                    if let Some(cycle) = visit(workflows, next, visits) {
+                        return Some(cycle);
+                    }
+                }
+                if instruction.condition == Condition::Always {
                     break;
                 }
             }
-        }
-    }
 
-    accepted_parts
-        .into_iter()
-        .map(|p| p.categories.iter().sum::<usize>())
-        .sum::<usize>()
-        .to_string()
-}
+            visits[entry] = Visit::Done;
+            None
+        }
 
-fn find_accepted(
-    workflows: &HashMap<&str, Workflow>,
-    current_workflow: &Workflow,
-    ranges: [[usize; 2]; 4],
-    valid_ranges: &mut Vec<[[usize; 2]; 4]>,
-) {
-    // The ranges that have not yet matched any rules
-    let mut current_ranges = ranges;
+        let mut visits = vec![Visit::New; self.code.len()];
+        self.entries()
+            .find_map(|entry| visit(self, entry, &mut visits))
+    }
 
-    for rule in current_workflow.rules.iter() {
-        // Index of range to be updated by rule
-        let i = rule.category;
+    fn accepts(&self, part: &Part) -> bool {
+        let mut pc = self.start;
+        loop {
+            let instruction = &self.code[pc];
+            if !instruction.matches(part) {
+                pc += 1;
+                continue;
+            }
+            match instruction.target {
+                Target::Jump(next) => pc = next,
+                Target::Rejected => return false,
+                Target::Accepted => return true,
+            }
+        }
+    }
 
-        // Work out the set of ranges that match this condition
-        let mut matched_ranges = current_ranges;
-        match rule.condition {
-            '<' => matched_ranges[i][1] = matched_ranges[i][1].min(rule.rhs - 1),
-            '>' => matched_ranges[i][0] = matched_ranges[i][0].max(rule.rhs + 1),
-            _ => unreachable!(),
+    /// Splits `ranges` into the boxes of ratings that end up accepted.
+    pub fn accepted_regions(
+        &self,
+        ranges: &[[usize; 2]],
+    ) -> Result<Vec<Region<'_>>, WrongRangeCount> {
+        if ranges.len() != self.categories.len() {
+            return Err(WrongRangeCount {
+                expected: self.categories.len(),
+                found: ranges.len(),
+            });
         }
 
-        // Calculate valid ranges for matches
-        match rule.destination {
-            Destination::Workflow(name) => find_accepted(
-                workflows,
-                workflows.get(name).unwrap(),
-                matched_ranges,
-                valid_ranges,
-            ),
-            Destination::Rejected => {}
-            Destination::Accepted => valid_ranges.push(matched_ranges),
+        let mut regions = Vec::new();
+        if ranges.iter().all(|range| range[0] <= range[1]) {
+            let mut path = vec![self.names[&self.start].as_str()];
+            self.find_accepted(self.start, ranges.to_vec(), &mut path, &mut regions);
         }
+        Ok(regions)
+    }
+
+    /// Number of combinations of ratings within `ranges` that are accepted.
+    pub fn count_accepted(&self, ranges: &[[usize; 2]]) -> Result<u128, WrongRangeCount> {
+        Ok(self
+            .accepted_regions(ranges)?
+            .iter()
+            .map(Region::combinations)
+            .sum())
+    }
+
+    fn find_accepted<'a>(
+        &'a self,
+        mut pc: usize,
+        ranges: Vec<[usize; 2]>,
+        path: &mut Vec<&'a str>,
+        regions: &mut Vec<Region<'a>>,
+    ) {
+        // The ranges that have not yet matched any rules
+        let mut current_ranges = ranges;
+
+        loop {
+            let instruction = self.code[pc];
+            if instruction.condition == Condition::Always {
+                self.dispatch(instruction.target, current_ranges, path, regions);
+                break;
+            }
 
-        // Update current ranges to those that don't match
-        match rule.condition {
-            '<' => current_ranges[i][0] = current_ranges[i][0].max(rule.rhs),
-            '>' => current_ranges[i][1] = current_ranges[i][1].min(rule.rhs),
-            _ => unreachable!(),
+            // Index of range to be updated by rule
+            let i = instruction.category;
+            let [min, max] = current_ranges[i];
+
+            let Some([lo, hi]) = instruction.condition.interval(instruction.rhs) else {
+                pc += 1;
+                continue;
+            };
+
+            // Work out the set of ranges that match this condition
+            if lo <= max && min <= hi {
+                let mut matched_ranges = current_ranges.clone();
+                matched_ranges[i] = [min.max(lo), max.min(hi)];
+                self.dispatch(instruction.target, matched_ranges, path, regions);
+            }
+
+            // Update current ranges to those that don't match, which for == may be on both
+            // sides of the matching ratings
+            let below = (min < lo).then(|| [min, max.min(lo - 1)]);
+            let above = (hi < max).then(|| [min.max(hi + 1), max]);
+            match (below, above) {
+                (Some(below), Some(above)) => {
+                    let mut below_ranges = current_ranges.clone();
+                    below_ranges[i] = below;
+                    self.find_accepted(pc + 1, below_ranges, path, regions);
+                    current_ranges[i] = above;
+                }
+                (Some(range), None) | (None, Some(range)) => current_ranges[i] = range,
+                (None, None) => break,
+            }
+            pc += 1;
         }
     }
 
-    match current_workflow.otherwise {
-        Destination::Workflow(name) => find_accepted(
-            workflows,
-            workflows.get(name).unwrap(),
-            current_ranges,
-            valid_ranges,
-        ),
-        Destination::Rejected => (),
-        Destination::Accepted => valid_ranges.push(current_ranges),
+    fn dispatch<'a>(
+        &'a self,
+        target: Target,
+        ranges: Vec<[usize; 2]>,
+        path: &mut Vec<&'a str>,
+        regions: &mut Vec<Region<'a>>,
+    ) {
+        match target {
+            Target::Jump(next) => {
+                path.push(&self.names[&next]);
+                self.find_accepted(next, ranges, path, regions);
+                path.pop();
+            }
+            Target::Rejected => {}
+            Target::Accepted => regions.push(Region {
+                ranges,
+                path: path.clone(),
+            }),
+        }
     }
 }
 
-pub fn part2(input: &str) -> String {
-    // Parse input
-    let (workflows, _) = input.trim().split_once("\n\n").unwrap();
-    let workflows: HashMap<_, _> = workflows.lines().map(Workflow::parse).collect();
+/// Parses the workflows and parts, along with the number of categories the parts rate that
+/// no workflow checks.
+fn parse_input(input: &str) -> Result<(Workflows, Vec<Part>, usize), ParseError> {
+    let (workflows, parts) = input
+        .trim()
+        .split_once("\n\n")
+        .ok_or(ParseError::MissingParts)?;
 
-    let ranges: [[usize; 2]; 4] = array_init::array_init(|_| [1, 4000]);
+    let workflows = Workflows::parse(workflows)?;
 
-    let mut valid_ranges = Vec::new();
-    let start_workflow = workflows.get("in").unwrap();
-    find_accepted(&workflows, start_workflow, ranges, &mut valid_ranges);
+    let ratings = parts
+        .lines()
+        .map(|line| Ok((line, parse_ratings(line)?)))
+        .collect::<Result<Vec<_>, ParseError>>()?;
 
-    valid_ranges
+    // Parts can rate categories that no workflow checks, but all parts must rate the same
+    let rated: BTreeSet<&str> = workflows
+        .categories
         .iter()
-        .map(|ranges| {
-            ranges
+        .map(String::as_str)
+        .chain(
+            ratings
                 .iter()
-                .filter(|range| range[0] <= range[1])
-                .map(|range| range[1] - range[0] + 1)
-                .product::<usize>()
+                .flat_map(|(_, ratings)| ratings.iter().map(|&(name, _)| name)),
+        )
+        .collect();
+
+    let parts = ratings
+        .into_iter()
+        .map(|(line, ratings)| {
+            let rating = |category: &str| {
+                ratings
+                    .iter()
+                    .find(|&&(name, _)| name == category)
+                    .map(|&(_, rating)| rating)
+                    .ok_or_else(|| ParseError::MissingRating {
+                        part: line.to_string(),
+                        category: category.to_string(),
+                    })
+            };
+            for category in rated.iter() {
+                rating(category)?;
+            }
+
+            Ok(Part {
+                ratings: workflows
+                    .categories
+                    .iter()
+                    .map(|category| rating(category))
+                    .collect::<Result<_, _>>()?,
+                total: ratings.iter().map(|&(_, rating)| rating).sum(),
+            })
         })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let unchecked = rated.len() - workflows.categories.len();
+    Ok((workflows, parts, unchecked))
+}
+
+pub fn part1(input: &str) -> String {
+    let (workflows, parts, _) = parse_input(input).expect("Invalid input");
+
+    parts
+        .into_iter()
+        .filter(|part| workflows.accepts(part))
+        .map(|part| part.total)
         .sum::<usize>()
         .to_string()
 }
 
+pub fn part2(input: &str) -> String {
+    let (workflows, _, unchecked) = parse_input(input).expect("Invalid input");
+
+    let ranges = vec![[1, 4000]; workflows.categories().len()];
+    let accepted = workflows
+        .count_accepted(&ranges)
+        .expect("A range per category");
+
+    // Every rating of a category that no workflow checks is accepted alike
+    u32::try_from(unchecked)
+        .ok()
+        .and_then(|unchecked| 4000u128.checked_pow(unchecked))
+        .and_then(|factor| accepted.checked_mul(factor))
+        .expect("Too many combinations")
+        .to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -251,4 +622,170 @@ hdj{m>838:A,pv}
     fn test_part2() {
         assert_eq!(&part2(TEST_INPUT), "167409079868000");
     }
+
+    const OPERATORS_INPUT: &str = r#"
+in{hue<=3:low,size==5:A,hue>=7:R,big}
+low{size==2:R,size<=4:A,R}
+big{size>=8:A,hue==5:A,R}
+
+{hue=2,size=3,weight=1}
+{size=5,hue=4,weight=1}
+{weight=1,hue=8,size=9}
+{hue=5,size=1,weight=10}
+"#;
+
+    fn brute_force(workflows: &Workflows, ranges: &[[usize; 2]]) -> u128 {
+        let mut count = 0;
+        let mut ratings: Vec<_> = ranges.iter().map(|range| range[0]).collect();
+        'outer: loop {
+            let part = Part {
+                ratings: ratings.clone(),
+                total: 0,
+            };
+            if workflows.accepts(&part) {
+                count += 1;
+            }
+
+            for (rating, range) in ratings.iter_mut().zip(ranges) {
+                if *rating < range[1] {
+                    *rating += 1;
+                    continue 'outer;
+                }
+                *rating = range[0];
+            }
+            return count;
+        }
+    }
+
+    #[test]
+    fn test_accepted_regions() {
+        let (workflows, _, _) = parse_input(TEST_INPUT).unwrap();
+        assert_eq!(workflows.categories(), ["a", "m", "s", "x"]);
+
+        let regions = workflows.accepted_regions(&[[1, 4000]; 4]).unwrap();
+        assert_eq!(
+            regions[0],
+            Region {
+                ranges: vec![[1, 2005], [1, 4000], [1, 1350], [1, 1415]],
+                path: vec!["in", "px", "qkq"],
+            }
+        );
+        assert_eq!(regions[1].path, vec!["in", "px", "qkq", "crn"]);
+        assert_eq!(
+            regions.iter().map(Region::combinations).sum::<u128>(),
+            167409079868000
+        );
+
+        assert!(workflows.accepted_regions(&[[5, 4]; 4]).unwrap().is_empty());
+        assert_eq!(
+            workflows.accepted_regions(&[[1, 4000]; 3]),
+            Err(WrongRangeCount {
+                expected: 4,
+                found: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_count_accepted() {
+        let (workflows, _, _) = parse_input(TEST_INPUT).unwrap();
+
+        let ranges = [[2000, 2010], [830, 845], [530, 540], [1400, 1420]];
+        assert_eq!(
+            workflows.count_accepted(&ranges),
+            Ok(brute_force(&workflows, &ranges))
+        );
+        assert_eq!(workflows.count_accepted(&[[0, 0]; 4]), Ok(1));
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(&part1(OPERATORS_INPUT), "32");
+
+        let (workflows, _, _) = parse_input(OPERATORS_INPUT).unwrap();
+        assert_eq!(workflows.categories(), ["hue", "size"]);
+
+        let ranges = [[0, 10], [0, 10]];
+        assert_eq!(
+            workflows.count_accepted(&ranges),
+            Ok(brute_force(&workflows, &ranges))
+        );
+
+        let ranges = [[5, 5], [0, usize::MAX]];
+        assert_eq!(workflows.count_accepted(&ranges), Ok(1 << 64));
+    }
+
+    #[test]
+    fn test_unchecked_categories() {
+        let input = "in{x<2001:A,R}\n\n{x=1,m=1,a=1,s=1}";
+        assert_eq!(part2(input), (2000 * 4000u128.pow(3)).to_string());
+        assert_eq!(&part1(input), "4");
+
+        // Weight is rated by the parts but checked by no workflow
+        let (workflows, _, unchecked) = parse_input(OPERATORS_INPUT).unwrap();
+        assert_eq!(unchecked, 1);
+        let accepted = workflows.count_accepted(&[[1, 4000]; 2]).unwrap();
+        assert_eq!(part2(OPERATORS_INPUT), (accepted * 4000).to_string());
+    }
+
+    #[test]
+    fn test_categories() {
+        // Sorted no matter the order rules check them in or parts rate them in
+        let input = "in{x<10:foo,m>5:A,R}\nfoo{s<3:R,A}\n\n{s=1,m=2,x=3,a=4}";
+        let workflows = Workflows::parse(input.split_once("\n\n").unwrap().0).unwrap();
+        assert_eq!(workflows.categories(), ["m", "s", "x"]);
+        let (workflows, parts, unchecked) = parse_input(input).unwrap();
+        assert_eq!(workflows.categories(), ["m", "s", "x"]);
+        assert_eq!(parts[0].ratings, [2, 1, 3]);
+        assert_eq!(parts[0].total, 10);
+        assert_eq!(unchecked, 1);
+    }
+
+    #[test]
+    fn test_validation() {
+        assert_eq!(
+            Workflows::parse("in{x<10:foo,A}").unwrap_err(),
+            ParseError::UnknownWorkflow("foo".to_string())
+        );
+        assert_eq!(
+            Workflows::parse("foo{A}").unwrap_err(),
+            ParseError::MissingStart
+        );
+        assert_eq!(
+            Workflows::parse("in{x<10:A,R}\nin{R}").unwrap_err(),
+            ParseError::DuplicateWorkflow("in".to_string())
+        );
+        assert_eq!(
+            Workflows::parse("in{x<10:foo,A}\nfoo{m>5:bar,R}\nbar{in}").unwrap_err(),
+            ParseError::Cycle("in".to_string())
+        );
+        assert_eq!(
+            Workflows::parse("in{x<10:A,foo}\nfoo{bar}\nbar{s>1:foo,R}").unwrap_err(),
+            ParseError::Cycle("foo".to_string())
+        );
+        assert_eq!(
+            Workflows::parse("in{<10:A,R}").unwrap_err(),
+            ParseError::InvalidRule("<10:A".to_string())
+        );
+        assert_eq!(
+            Workflows::parse("in{x=<10:A,R}").unwrap_err(),
+            ParseError::InvalidRule("x=<10:A".to_string())
+        );
+        assert_eq!(
+            Workflows::parse("in{x<10:A}").unwrap_err(),
+            ParseError::InvalidWorkflow("in{x<10:A}".to_string())
+        );
+        assert_eq!(
+            parse_input("in{x<10:A,R}\n\n{x=1,x=2}").unwrap_err(),
+            ParseError::InvalidPart("{x=1,x=2}".to_string())
+        );
+        assert_eq!(
+            parse_input("in{x<10:A,R}\n\n{x=1}\n{m=2,x=3}").unwrap_err(),
+            ParseError::MissingRating {
+                part: "{x=1}".to_string(),
+                category: "m".to_string()
+            }
+        );
+        assert!(Workflows::parse("in{x<10:foo,foo}\nfoo{A}").is_ok());
+    }
 }
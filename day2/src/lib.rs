@@ -1,86 +1,248 @@
-#[derive(Default)]
-struct Round {
-    red: u32,
-    green: u32,
-    blue: u32,
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    InvalidGame(String),
+    InvalidId(String),
+    InvalidPick(String),
+    DuplicateColour(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidGame(line) => write!(f, "invalid game: {:?}", line),
+            ParseError::InvalidId(id) => write!(f, "invalid game ID: {:?}", id),
+            ParseError::InvalidPick(pick) => write!(f, "invalid pick: {:?}", pick),
+            ParseError::DuplicateColour(colour) => {
+                write!(f, "colour {:?} is picked twice in one round", colour)
+            }
+        }
+    }
 }
 
-struct Game {
-    id: u32,
-    rounds: Vec<Round>,
+impl std::error::Error for ParseError {}
+
+/// Colours of the cubes in the bag from part 1.
+const STANDARD_COLOURS: [&str; 3] = ["red", "green", "blue"];
+
+/// Numbers of cubes of each colour, in order of first appearance. Missing colours have no
+/// cubes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cubes {
+    counts: Vec<(String, u32)>,
 }
 
-fn parse_round(input: &str) -> Round {
-    let mut round = Round::default();
+impl Cubes {
+    /// The bag from part 1.
+    pub fn standard_bag() -> Self {
+        let mut bag = Self::default();
+        for (colour, count) in STANDARD_COLOURS.into_iter().zip([12, 13, 14]) {
+            bag.set(colour, count);
+        }
+        bag
+    }
+
+    pub fn count(&self, colour: &str) -> u32 {
+        self.counts
+            .iter()
+            .find(|(c, _)| c == colour)
+            .map_or(0, |&(_, count)| count)
+    }
+
+    pub fn set(&mut self, colour: &str, count: u32) {
+        match self.counts.iter_mut().find(|(c, _)| c == colour) {
+            Some((_, c)) => *c = count,
+            None => self.counts.push((colour.to_string(), count)),
+        }
+    }
+
+    pub fn colours(&self) -> impl Iterator<Item = (&str, u32)> + '_ {
+        self.counts
+            .iter()
+            .map(|(colour, count)| (colour.as_str(), *count))
+    }
+
+    /// Whether these cubes could all be drawn from `bag` at once.
+    pub fn fits_in(&self, bag: &Cubes) -> bool {
+        self.colours()
+            .all(|(colour, count)| count <= bag.count(colour))
+    }
+
+    /// Product of the number of cubes of red, green, blue and any other colour, so the
+    /// power is 0 if one of the standard colours has no cubes. None if it overflows.
+    pub fn power(&self) -> Option<u64> {
+        let others = self
+            .colours()
+            .map(|(colour, _)| colour)
+            .filter(|colour| !STANDARD_COLOURS.contains(colour));
+
+        STANDARD_COLOURS
+            .into_iter()
+            .chain(others)
+            .try_fold(1u64, |power, colour| {
+                power.checked_mul(self.count(colour) as u64)
+            })
+    }
+}
+
+impl fmt::Display for Cubes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (colour, count)) in self.colours().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {}", count, colour)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Game {
+    pub id: u32,
+    pub rounds: Vec<Cubes>,
+}
+
+impl Game {
+    /// Whether every round could have been drawn from `bag`.
+    pub fn possible_with(&self, bag: &Cubes) -> bool {
+        self.rounds.iter().all(|round| round.fits_in(bag))
+    }
+
+    /// The fewest cubes of each colour that make the game possible.
+    pub fn minimal_bag(&self) -> Cubes {
+        let mut bag = Cubes::default();
+        for (colour, count) in self.rounds.iter().flat_map(Cubes::colours) {
+            if count >= bag.count(colour) {
+                bag.set(colour, count);
+            }
+        }
+        bag
+    }
+}
+
+fn parse_round(input: &str) -> Result<Cubes, ParseError> {
+    let mut round = Cubes::default();
 
     for pick in input.split(',').map(|pick| pick.trim()) {
-        let (count, colour) = pick.split_once(' ').unwrap();
-        let count = count.parse::<u32>().expect("Invalid number");
-
-        match colour {
-            "red" => round.red = count,
-            "green" => round.green = count,
-            "blue" => round.blue = count,
-            _ => panic!("Unknown colour"),
+        let invalid = || ParseError::InvalidPick(pick.to_string());
+        let (count, colour) = pick.split_once(' ').ok_or_else(invalid)?;
+        let count = count.parse::<u32>().map_err(|_| invalid())?;
+        let colour = colour.trim();
+        if colour.is_empty() {
+            return Err(invalid());
         }
+
+        if round.colours().any(|(c, _)| c == colour) {
+            return Err(ParseError::DuplicateColour(colour.to_string()));
+        }
+        round.set(colour, count);
     }
-    round
+    Ok(round)
 }
 
-fn parse_game(input: &str) -> Game {
-    let (game, rounds) = input.split_once(':').unwrap();
+fn parse_game(input: &str) -> Result<Game, ParseError> {
+    let (game, rounds) = input
+        .split_once(':')
+        .ok_or_else(|| ParseError::InvalidGame(input.to_string()))?;
 
     // Extract game ID
     let id = game
-        .split_whitespace()
-        .last()
-        .unwrap()
-        .parse::<u32>()
-        .unwrap();
+        .strip_prefix("Game")
+        .and_then(|id| id.trim().parse::<u32>().ok())
+        .ok_or_else(|| ParseError::InvalidId(game.to_string()))?;
 
     // Parse rounds separated by ';'
-    let rounds: Vec<_> = rounds.split(';').map(parse_round).collect();
+    let rounds = rounds
+        .split(';')
+        .map(parse_round)
+        .collect::<Result<_, _>>()?;
 
-    Game { id, rounds }
+    Ok(Game { id, rounds })
 }
 
-fn game_possible(game: &Game) -> bool {
-    const MAX_RED: u32 = 12;
-    const MAX_GREEN: u32 = 13;
-    const MAX_BLUE: u32 = 14;
+pub fn parse_games(input: &str) -> Result<Vec<Game>, ParseError> {
+    input
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(parse_game)
+        .collect()
+}
+
+/// Games that could have been played with `bag`.
+pub fn possible_games<'a>(games: &'a [Game], bag: &'a Cubes) -> impl Iterator<Item = &'a Game> {
+    games.iter().filter(|game| game.possible_with(bag))
+}
 
-    !game
-        .rounds
+/// The fewest cubes of each colour that make the game with ID `id` possible.
+pub fn minimal_bag(games: &[Game], id: u32) -> Option<Cubes> {
+    games
         .iter()
-        .any(|r| r.red > MAX_RED || r.green > MAX_GREEN || r.blue > MAX_BLUE)
+        .find(|game| game.id == id)
+        .map(Game::minimal_bag)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionError {
+    Unknown(String),
+    InvalidValue { key: String, value: String },
+    InvalidInput(ParseError),
 }
 
+impl fmt::Display for OptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptionError::Unknown(key) => write!(f, "unknown option {:?}", key),
+            OptionError::InvalidValue { key, value } => {
+                write!(f, "invalid value {:?} for option {:?}", value, key)
+            }
+            OptionError::InvalidInput(error) => write!(f, "invalid input: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for OptionError {}
+
 pub fn part1(input: &str) -> String {
-    input
-        .lines()
-        .filter(|line| !line.is_empty())
-        .map(parse_game)
-        .filter(game_possible)
+    let games = parse_games(input).expect("Invalid games");
+    possible_games(&games, &Cubes::standard_bag())
         .map(|game| game.id)
         .sum::<u32>()
         .to_string()
 }
 
-pub fn part2(input: &str) -> String {
-    input
-        .lines()
-        .filter(|line| !line.is_empty())
-        .map(parse_game)
-        .map(|game| {
-            let rounds = || game.rounds.iter();
+/// Part 1 with the number of cubes of each colour in the bag changed by options such as
+/// `red=20` or `purple=3`.
+pub fn part1_with_options(input: &str, options: &[(&str, &str)]) -> Result<String, OptionError> {
+    let mut bag = Cubes::standard_bag();
 
-            let max_red = rounds().map(|r| r.red).max().unwrap_or(0);
-            let max_green = rounds().map(|r| r.green).max().unwrap_or(0);
-            let max_blue = rounds().map(|r| r.blue).max().unwrap_or(0);
+    for &(colour, count) in options {
+        if colour.is_empty() || !colour.chars().all(char::is_alphabetic) {
+            return Err(OptionError::Unknown(colour.to_string()));
+        }
+        let count = count.parse().map_err(|_| OptionError::InvalidValue {
+            key: colour.to_string(),
+            value: count.to_string(),
+        })?;
+        bag.set(colour, count);
+    }
 
-            max_red * max_green * max_blue
-        })
+    let games = parse_games(input).map_err(OptionError::InvalidInput)?;
+    Ok(possible_games(&games, &bag)
+        .map(|game| game.id)
         .sum::<u32>()
+        .to_string())
+}
+
+pub fn part2(input: &str) -> String {
+    let games = parse_games(input).expect("Invalid games");
+    games
+        .iter()
+        .try_fold(0u64, |sum, game| {
+            sum.checked_add(game.minimal_bag().power()?)
+        })
+        .expect("Sum of powers overflows")
         .to_string()
 }
 
@@ -105,4 +267,114 @@ Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green
     fn test_part2() {
         assert_eq!(&part2(TEST_INPUT), "2286");
     }
+
+    #[test]
+    fn test_queries() {
+        let games = parse_games(TEST_INPUT).unwrap();
+
+        let mut bag = Cubes::default();
+        bag.set("red", 20);
+        bag.set("green", 13);
+        bag.set("blue", 6);
+        let ids: Vec<_> = possible_games(&games, &bag).map(|game| game.id).collect();
+        assert_eq!(ids, [1, 2, 3, 5]);
+
+        let bag = minimal_bag(&games, 4).unwrap();
+        assert_eq!(bag.to_string(), "3 green, 14 red, 15 blue");
+        assert_eq!(bag.power(), Some(630));
+        assert!(games[3].possible_with(&bag));
+        assert_eq!(minimal_bag(&games, 6), None);
+    }
+
+    #[test]
+    fn test_colours() {
+        let games = parse_games(
+            "Game 1: 2 purple, 1 red; 3 purple\nGame 2: 1 orange\nGame 3: 0 orange, 4 red\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            minimal_bag(&games, 1).unwrap().to_string(),
+            "3 purple, 1 red"
+        );
+        let ids: Vec<_> = possible_games(&games, &Cubes::standard_bag())
+            .map(|game| game.id)
+            .collect();
+        assert_eq!(ids, [3]);
+
+        let mut bag = Cubes::standard_bag();
+        bag.set("purple", 3);
+        assert!(games[0].possible_with(&bag));
+        assert!(!games[1].possible_with(&bag));
+    }
+
+    #[test]
+    fn test_power() {
+        let games = parse_games(
+            "Game 1: 2 red, 3 green, 4 blue, 5 purple
+Game 2: 2 red, 4 blue
+Game 3: 2 red, 0 green, 4 blue
+",
+        )
+        .unwrap();
+        let powers: Vec<_> = games
+            .iter()
+            .map(|game| game.minimal_bag().power())
+            .collect();
+        assert_eq!(powers, [Some(120), Some(0), Some(0)]);
+
+        let mut bag = Cubes::default();
+        for colour in STANDARD_COLOURS {
+            bag.set(colour, 1 << 20);
+        }
+        assert_eq!(bag.power(), Some(1 << 60));
+        bag.set("purple", 16);
+        assert_eq!(bag.power(), None);
+    }
+
+    #[test]
+    fn test_options() {
+        assert_eq!(part1_with_options(TEST_INPUT, &[]), Ok("8".to_string()));
+        assert_eq!(
+            part1_with_options(TEST_INPUT, &[("red", "20"), ("blue", "6")]),
+            Ok("11".to_string())
+        );
+        assert_eq!(
+            part1_with_options(TEST_INPUT, &[("blue", "many")]),
+            Err(OptionError::InvalidValue {
+                key: "blue".to_string(),
+                value: "many".to_string()
+            })
+        );
+        assert_eq!(
+            part1_with_options(TEST_INPUT, &[("2", "3")]),
+            Err(OptionError::Unknown("2".to_string()))
+        );
+        assert_eq!(
+            part1_with_options("Game one: 3 blue", &[]),
+            Err(OptionError::InvalidInput(ParseError::InvalidId(
+                "Game one".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            parse_games("Game 1 3 blue"),
+            Err(ParseError::InvalidGame("Game 1 3 blue".to_string()))
+        );
+        assert_eq!(
+            parse_games("Round 1: 3 blue"),
+            Err(ParseError::InvalidId("Round 1".to_string()))
+        );
+        assert_eq!(
+            parse_games("Game 1: 3 blue, red"),
+            Err(ParseError::InvalidPick("red".to_string()))
+        );
+        assert_eq!(
+            parse_games("Game 1: 3 blue; 1 red, 2 red"),
+            Err(ParseError::DuplicateColour("red".to_string()))
+        );
+    }
 }
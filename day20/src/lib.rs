@@ -1,7 +1,11 @@
-use std::collections::{HashMap, VecDeque};
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Pulse {
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    fmt::{self, Write},
+    hash::{Hash, Hasher},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pulse {
     Low,
     High,
 }
@@ -107,123 +111,639 @@ fn parse_modules(input: &str) -> HashMap<&str, Module<'_>> {
     modules
 }
 
-pub fn part1(input: &str) -> String {
-    let mut modules = parse_modules(input);
+fn write_dot(modules: &HashMap<&str, Module>, show_state: bool) -> String {
+    let mut names: Vec<_> = modules.keys().copied().collect();
+    names.sort_unstable();
 
-    let mut low_pulse_count = 0;
-    let mut high_pulse_count = 0;
+    let mut dot = String::from("digraph modules {\n");
+    writeln!(dot, "    button [shape=point];").unwrap();
+
+    for name in names.iter() {
+        let (shape, prefix, state) = match &modules[name].logic {
+            Logic::Broadcast => ("doublecircle", "", ""),
+            Logic::FlipFlop { on } => ("box", "%", if *on { "\\non" } else { "\\noff" }),
+            Logic::Conjunction { .. } => ("invhouse", "&", ""),
+        };
+        let state = if show_state { state } else { "" };
+        writeln!(
+            dot,
+            "    \"{}\" [shape={}, label=\"{}{}{}\"];",
+            name, shape, prefix, name, state
+        )
+        .unwrap();
+    }
 
-    // Queue of modules to visit and the state to apply
-    let mut queue: VecDeque<(&str, &str, Pulse)> = VecDeque::new();
+    // Outputs that aren't modules themselves, such as rx
+    let mut sinks: Vec<_> = modules
+        .values()
+        .flat_map(|module| module.outputs.iter().copied())
+        .filter(|output| !modules.contains_key(output))
+        .collect();
+    sinks.sort_unstable();
+    sinks.dedup();
+    for sink in sinks {
+        writeln!(dot, "    \"{}\" [shape=plaintext];", sink).unwrap();
+    }
+
+    writeln!(dot, "    button -> broadcaster;").unwrap();
+    for name in names.iter() {
+        for output in modules[name].outputs.iter() {
+            let remembered = match modules.get(output).map(|m| &m.logic) {
+                Some(Logic::Conjunction { input_states }) if show_state => input_states.get(name),
+                _ => None,
+            };
+            match remembered {
+                Some(Pulse::Low) => {
+                    writeln!(dot, "    \"{}\" -> \"{}\" [label=low];", name, output).unwrap()
+                }
+                Some(Pulse::High) => writeln!(
+                    dot,
+                    "    \"{}\" -> \"{}\" [label=high, color=red];",
+                    name, output
+                )
+                .unwrap(),
+                None => writeln!(dot, "    \"{}\" -> \"{}\";", name, output).unwrap(),
+            }
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event<'a> {
+    pub sender: &'a str,
+    pub receiver: &'a str,
+    pub pulse: Pulse,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PulseCounts {
+    pub low: usize,
+    pub high: usize,
+}
+
+/// Flip-flop and conjunction memory, in a fixed module order.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Snapshot(Vec<bool>);
+
+/// The button was pressed before the pulses from the last press were all sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PulsesInFlight;
+
+impl fmt::Display for PulsesInFlight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "button pressed while pulses are in flight")
+    }
+}
+
+impl std::error::Error for PulsesInFlight {}
+
+/// Pulse network simulator which can be pressed, stepped through and stopped at watchpoints.
+pub struct Network<'a> {
+    modules: HashMap<&'a str, Module<'a>>,
+    /// Module names in the order used for snapshots.
+    order: Vec<&'a str>,
+    queue: VecDeque<Event<'a>>,
+    presses: usize,
+    counts: PulseCounts,
+    trace: Option<Vec<Event<'a>>>,
+    watchpoints: Vec<(&'a str, Pulse)>,
+}
+
+impl<'a> Network<'a> {
+    pub fn parse(input: &'a str) -> Self {
+        let modules = parse_modules(input);
+
+        let mut order: Vec<_> = modules.keys().copied().collect();
+        order.sort_unstable();
+
+        Self {
+            modules,
+            order,
+            queue: VecDeque::new(),
+            presses: 0,
+            counts: PulseCounts::default(),
+            trace: None,
+            watchpoints: Vec::new(),
+        }
+    }
+
+    /// Number of times the button has been pressed.
+    pub fn presses(&self) -> usize {
+        self.presses
+    }
+
+    /// Pulses sent since the button was last pressed.
+    pub fn counts(&self) -> PulseCounts {
+        self.counts
+    }
+
+    /// Starts or stops recording every pulse sent.
+    pub fn set_trace(&mut self, enabled: bool) {
+        self.trace = enabled.then(Vec::new);
+    }
+
+    /// Takes the pulses recorded since tracing started or the trace was last taken.
+    pub fn take_trace(&mut self) -> Vec<Event<'a>> {
+        self.trace.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Stops the simulation whenever `receiver` is sent `pulse`.
+    pub fn watch(&mut self, receiver: &'a str, pulse: Pulse) {
+        self.watchpoints.push((receiver, pulse));
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Sends one pulse from the queue, returning it if there was any.
+    pub fn step(&mut self) -> Option<Event<'a>> {
+        let event = self.queue.pop_front()?;
+
+        // Update pulse count
+        match event.pulse {
+            Pulse::Low => self.counts.low += 1,
+            Pulse::High => self.counts.high += 1,
+        }
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(event);
+        }
+
+        // Get module
+        if let Some(module) = self.modules.get_mut(event.receiver) {
+            // Update module state with pulse
+            if let Some(pulse) = module.update(event.sender, event.pulse) {
+                // Propagate pulse
+                for output in module.outputs.iter() {
+                    self.queue.push_back(Event {
+                        sender: module.name,
+                        receiver: output,
+                        pulse,
+                    });
+                }
+            }
+        }
+
+        Some(event)
+    }
+
+    /// Steps until the network is idle, or returns the pulse that hit a watchpoint. Calling
+    /// again carries on from where it stopped.
+    pub fn run(&mut self) -> Option<Event<'a>> {
+        while let Some(event) = self.step() {
+            if self
+                .watchpoints
+                .iter()
+                .any(|&(receiver, pulse)| receiver == event.receiver && pulse == event.pulse)
+            {
+                return Some(event);
+            }
+        }
+        None
+    }
+
+    /// Presses the button and runs the network, returning a pulse if it hit a watchpoint.
+    ///
+    /// The network must be idle, so after stopping at a watchpoint it has to be run until
+    /// it stops again without one.
+    pub fn press_button(&mut self) -> Result<Option<Event<'a>>, PulsesInFlight> {
+        if !self.is_idle() {
+            return Err(PulsesInFlight);
+        }
+
+        self.presses += 1;
+        self.counts = PulseCounts::default();
 
-    // Push the button 1000 times
-    for _ in 0..1000 {
         // Button connected to broadcast modules
-        queue.clear();
-        queue.push_back(("broadcaster", "", Pulse::Low));
-
-        while let Some((name, input, pulse)) = queue.pop_front() {
-            // Update pulse count
-            match pulse {
-                Pulse::Low => low_pulse_count += 1,
-                Pulse::High => high_pulse_count += 1,
-            }
-
-            // Get module
-            if let Some(module) = modules.get_mut(name) {
-                // Update module state with pulse
-                if let Some(pulse) = module.update(input, pulse) {
-                    // Propagate pulse
-                    for output in module.outputs.iter() {
-                        queue.push_back((output, name, pulse));
-                    }
+        self.queue.push_back(Event {
+            sender: "button",
+            receiver: "broadcaster",
+            pulse: Pulse::Low,
+        });
+
+        Ok(self.run())
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let mut state = Vec::new();
+        for name in self.order.iter() {
+            match &self.modules[name].logic {
+                Logic::Broadcast => {}
+                Logic::FlipFlop { on } => state.push(*on),
+                Logic::Conjunction { input_states } => {
+                    let mut inputs: Vec<_> = input_states.iter().collect();
+                    inputs.sort_unstable_by_key(|(input, _)| **input);
+                    state.extend(inputs.into_iter().map(|(_, pulse)| *pulse == Pulse::High));
                 }
             }
         }
+        Snapshot(state)
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.snapshot().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Renders the network in Graphviz DOT format, optionally with flip-flops labelled on or
+    /// off and edges into conjunctions labelled with the last pulse remembered.
+    pub fn to_dot(&self, show_state: bool) -> String {
+        write_dot(&self.modules, show_state)
+    }
+}
+
+/// Renders the module network in Graphviz DOT format, after `presses` button presses if
+/// given.
+pub fn to_dot(input: &str, presses: Option<usize>) -> String {
+    let mut network = Network::parse(input);
+
+    if let Some(presses) = presses {
+        for _ in 0..presses {
+            network.press_button().expect("No watchpoints are set");
+        }
+    }
+
+    network.to_dot(presses.is_some())
+}
+
+pub fn part1(input: &str) -> String {
+    let mut network = Network::parse(input);
+
+    let mut low_pulse_count = 0;
+    let mut high_pulse_count = 0;
+
+    // Push the button 1000 times
+    for _ in 0..1000 {
+        network.press_button().expect("No watchpoints are set");
+        low_pulse_count += network.counts().low;
+        high_pulse_count += network.counts().high;
     }
 
     (low_pulse_count * high_pulse_count).to_string()
 }
 
-fn gcd(mut a: usize, mut b: usize) -> usize {
-    while b != 0 {
-        let t = b;
-        b = a % b;
-        a = t;
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnalysisError {
+    NoRx,
+    /// rx must be fed by exactly one conjunction, whose inputs are the sub-circuits.
+    UnsupportedRxInput,
+    /// Simulation gave up before seeing an input of the final conjunction repeat.
+    NoCycle {
+        input: String,
+        presses: usize,
+    },
+    /// An input of the final conjunction sent high pulses at uneven intervals.
+    IrregularCycle {
+        input: String,
+    },
+    ZeroPeriod,
+    NeverSynchronises,
+    Overflow,
+}
+
+impl fmt::Display for AnalysisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnalysisError::NoRx => write!(f, "no module sends pulses to rx"),
+            AnalysisError::UnsupportedRxInput => {
+                write!(f, "rx must be fed by a single conjunction")
+            }
+            AnalysisError::NoCycle { input, presses } => write!(
+                f,
+                "{} didn't send a repeating high pulse within {} presses",
+                input, presses
+            ),
+            AnalysisError::IrregularCycle { input } => {
+                write!(f, "{} sent high pulses at uneven intervals", input)
+            }
+            AnalysisError::ZeroPeriod => write!(f, "cycle has a period of 0"),
+            AnalysisError::NeverSynchronises => {
+                write!(
+                    f,
+                    "the inputs to rx never send high pulses on the same press"
+                )
+            }
+            AnalysisError::Overflow => write!(f, "arithmetic overflow"),
+        }
     }
-    a
 }
 
-fn lcm(numbers: &[usize]) -> usize {
-    let mut res = numbers[0];
+impl std::error::Error for AnalysisError {}
 
-    for x in numbers.iter().skip(1) {
-        res = x * res / gcd(*x, res);
+/// Presses on which a sub-circuit sends a high pulse: `first`, then every `period` after.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycle {
+    pub first: u64,
+    pub period: u64,
+}
+
+/// Presses to simulate before giving up on finding cycles.
+const MAX_SIMULATED_PRESSES: usize = 1 << 16;
+
+fn inputs_of<'a>(modules: &HashMap<&'a str, Module<'a>>) -> HashMap<&'a str, Vec<&'a str>> {
+    let mut inputs: HashMap<&str, Vec<&str>> = HashMap::new();
+    for module in modules.values() {
+        for output in module.outputs.iter() {
+            inputs.entry(output).or_default().push(module.name);
+        }
     }
+    inputs
+}
+
+/// The conjunction feeding rx.
+fn final_conjunction<'a>(modules: &HashMap<&'a str, Module<'a>>) -> Result<&'a str, AnalysisError> {
+    let inputs = inputs_of(modules);
+    let feeding = inputs.get("rx").ok_or(AnalysisError::NoRx)?;
 
-    res
+    match feeding[..] {
+        [name] if matches!(modules[name].logic, Logic::Conjunction { .. }) => Ok(name),
+        _ => Err(AnalysisError::UnsupportedRxInput),
+    }
 }
 
-pub fn part2(input: &str) -> String {
-    let mut modules = parse_modules(input);
+/// Reads the period of the binary counter started by `start`, which must feed `final_name`.
+///
+/// A counter is a chain of flip-flops, each bit optionally feeding a shared hub conjunction.
+/// The hub sends a low pulse once every bit feeding it is on, and resets the counter by
+/// sending low pulses to every other bit and the lowest one. The bits feeding the hub
+/// therefore spell out the period. An odd number of inverters must then carry the pulse
+/// to the final conjunction as a high pulse.
+fn counter_cycle(
+    modules: &HashMap<&str, Module>,
+    inputs: &HashMap<&str, Vec<&str>>,
+    start: &str,
+    final_name: &str,
+) -> Option<(String, Cycle)> {
+    let is_flip_flop = |name: &str| {
+        matches!(
+            modules.get(name).map(|m| &m.logic),
+            Some(Logic::FlipFlop { .. })
+        )
+    };
 
-    // Push the button 1000 times
-    let mut button_count = 0;
+    // Walk the chain of bits
+    let mut bits = Vec::new();
+    let mut hub = None;
+    let mut current = start;
+    loop {
+        let module = modules.get(current)?;
+        if !is_flip_flop(current) {
+            return None;
+        }
+
+        let (next, others): (Vec<&str>, Vec<&str>) = module
+            .outputs
+            .iter()
+            .copied()
+            .partition(|&output| is_flip_flop(output));
+        let feeds_hub = match others[..] {
+            [] => false,
+            [other] if hub.is_none_or(|hub| hub == other) => {
+                hub = Some(other);
+                true
+            }
+            _ => return None,
+        };
+        bits.push((current, feeds_hub));
 
-    // Queue of modules to visit and the state to apply
-    let mut queue: VecDeque<(&str, &str, Pulse)> = VecDeque::new();
+        match next[..] {
+            [] => break,
+            [next] => current = next,
+            _ => return None,
+        }
+    }
+    let hub = hub?;
 
-    // Module that outputs rx is a conjunction
-    // Find all inputs to that conjunction
-    let final_module = modules
-        .values()
-        .find(|m| m.outputs.contains(&"rx"))
-        .unwrap();
-    let final_module_name = final_module.name;
+    // The hub must listen to exactly the bits feeding it, and reset all the others
+    let hub_module = modules.get(hub)?;
+    if !matches!(hub_module.logic, Logic::Conjunction { .. }) {
+        return None;
+    }
+    let mut hub_inputs = inputs[hub].clone();
+    let mut feeding: Vec<_> = bits.iter().filter(|(_, f)| *f).map(|(n, _)| *n).collect();
+    hub_inputs.sort_unstable();
+    feeding.sort_unstable();
+    if hub_inputs != feeding {
+        return None;
+    }
+
+    let mut resets: Vec<_> = bits
+        .iter()
+        .enumerate()
+        .filter(|(i, (_, feeds_hub))| *i == 0 || !feeds_hub)
+        .map(|(_, (name, _))| *name)
+        .collect();
+    let (mut hub_bits, onward): (Vec<&str>, Vec<&str>) = hub_module
+        .outputs
+        .iter()
+        .copied()
+        .partition(|output| bits.iter().any(|(name, _)| name == output));
+    resets.sort_unstable();
+    hub_bits.sort_unstable();
+    if resets != hub_bits || !bits[0].1 {
+        return None;
+    }
 
-    // We need each of these input states to be high
-    let input_states = match &final_module.logic {
-        Logic::Conjunction { input_states } => input_states,
-        _ => unreachable!(),
+    // Follow inverters to the final conjunction
+    let mut inverted = false;
+    let mut current = match onward[..] {
+        [onward] => onward,
+        _ => return None,
     };
+    let mut previous = hub;
+    while current != final_name {
+        let module = modules.get(current)?;
+        match (&module.logic, &module.outputs[..], &inputs[current][..]) {
+            (Logic::Conjunction { .. }, [next], [_]) => {
+                inverted = !inverted;
+                previous = current;
+                current = next;
+            }
+            _ => return None,
+        }
+    }
+    if !inverted {
+        return None;
+    }
 
-    let mut cycle_lengths: HashMap<&str, Option<usize>> =
-        input_states.iter().map(|(name, _)| (*name, None)).collect();
+    let period = bits
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, feeds_hub))| *feeds_hub)
+        .map(|(i, _)| 1u64.checked_shl(i as u32))
+        .sum::<Option<u64>>()?;
+
+    Some((
+        previous.to_string(),
+        Cycle {
+            first: period,
+            period,
+        },
+    ))
+}
 
-    loop {
-        // Button connected to broadcast modules
-        queue.clear();
-        queue.push_back(("broadcaster", "", Pulse::Low));
-        button_count += 1;
-
-        while let Some((name, input, pulse)) = queue.pop_front() {
-            if name == final_module_name && pulse == Pulse::High {
-                // Find first time input is high
-                if let Some(cycle_length) = cycle_lengths.get_mut(input) {
-                    if cycle_length.is_none() {
-                        *cycle_length = Some(button_count);
-                    }
-                }
+/// Reads every sub-circuit feeding the final conjunction as a counter, if they all are.
+fn analyse_counters(
+    modules: &HashMap<&str, Module>,
+    final_name: &str,
+) -> Option<HashMap<String, Cycle>> {
+    let inputs = inputs_of(modules);
+    let starts = &modules.get("broadcaster")?.outputs;
+
+    let mut cycles = HashMap::new();
+    for start in starts {
+        let (input, cycle) = counter_cycle(modules, &inputs, start, final_name)?;
+        cycles.insert(input, cycle);
+    }
 
-                // If all cycles have been discovered
-                if cycle_lengths.values().all(|c| c.is_some()) {
-                    let cycle_lengths: Vec<_> = cycle_lengths.values().copied().flatten().collect();
-                    return lcm(&cycle_lengths).to_string();
-                }
+    // Every input must be driven by its own counter
+    let mut expected = inputs[final_name].clone();
+    expected.sort_unstable();
+    let mut found: Vec<_> = cycles.keys().map(String::as_str).collect();
+    found.sort_unstable();
+    (expected == found).then_some(cycles)
+}
+
+/// Presses the button until every input of the final conjunction has sent three high
+/// pulses, so each period is seen twice before it's trusted.
+fn simulate_cycles(input: &str, final_name: &str) -> Result<HashMap<String, Cycle>, AnalysisError> {
+    let mut network = Network::parse(input);
+    network.watch(final_name, Pulse::High);
+
+    let inputs = inputs_of(&network.modules);
+    let mut hits: HashMap<&str, Vec<u64>> = inputs[final_name]
+        .iter()
+        .map(|name| (*name, Vec::new()))
+        .collect();
+
+    while hits.values().any(|hits| hits.len() < 3) {
+        if network.presses() == MAX_SIMULATED_PRESSES {
+            let (input, _) = hits.iter().find(|(_, hits)| hits.len() < 3).unwrap();
+            return Err(AnalysisError::NoCycle {
+                input: input.to_string(),
+                presses: MAX_SIMULATED_PRESSES,
+            });
+        }
+
+        let mut hit = network
+            .press_button()
+            .expect("Network runs until idle after every hit");
+        while let Some(event) = hit {
+            let presses = network.presses() as u64;
+            let hits = hits.get_mut(event.sender).unwrap();
+            if hits.len() < 3 && hits.last() != Some(&presses) {
+                hits.push(presses);
             }
+            hit = network.run();
+        }
+    }
 
-            // Get module
-            if let Some(module) = modules.get_mut(name) {
-                // Update module state with pulse
-                if let Some(pulse) = module.update(input, pulse) {
-                    // Propagate pulse
-                    for output in module.outputs.iter() {
-                        queue.push_back((output, name, pulse));
-                    }
-                }
+    hits.into_iter()
+        .map(|(input, hits)| {
+            let period = hits[1] - hits[0];
+            if hits[2] - hits[1] != period {
+                return Err(AnalysisError::IrregularCycle {
+                    input: input.to_string(),
+                });
             }
+            let cycle = Cycle {
+                first: hits[0],
+                period,
+            };
+            Ok((input.to_string(), cycle))
+        })
+        .collect()
+}
+
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
+}
+
+/// First press on which every cycle sends a high pulse, combining them with the Chinese
+/// remainder theorem.
+pub fn combine_cycles(cycles: &[Cycle]) -> Result<u64, AnalysisError> {
+    // Solution so far is every press congruent to `remainder` modulo `modulus`
+    let mut remainder: i128 = 0;
+    let mut modulus: i128 = 1;
+
+    for cycle in cycles {
+        if cycle.period == 0 {
+            return Err(AnalysisError::ZeroPeriod);
         }
+        let period = cycle.period as i128;
+        let offset = cycle.first as i128 % period;
+
+        let (g, x, _) = extended_gcd(modulus, period);
+        if (offset - remainder) % g != 0 {
+            return Err(AnalysisError::NeverSynchronises);
+        }
+
+        // Every value is below the moduli so far, but their products may not fit
+        let step = period / g;
+        let k = ((offset - remainder) / g % step)
+            .checked_mul(x % step)
+            .ok_or(AnalysisError::Overflow)?
+            .rem_euclid(step);
+        remainder = modulus
+            .checked_mul(k)
+            .and_then(|shift| remainder.checked_add(shift))
+            .ok_or(AnalysisError::Overflow)?;
+        modulus = modulus.checked_mul(step).ok_or(AnalysisError::Overflow)?;
+        remainder %= modulus;
     }
+
+    // Cycles only begin once they've first fired
+    let start = cycles.iter().map(|cycle| cycle.first).max().unwrap_or(0) as i128;
+    let presses = if remainder >= start {
+        Some(remainder)
+    } else {
+        // Rounds up the number of cycles needed to reach the start
+        (start - remainder)
+            .checked_add(modulus - 1)
+            .map(|gap| gap / modulus)
+            .and_then(|cycles_needed| cycles_needed.checked_mul(modulus))
+            .and_then(|shift| remainder.checked_add(shift))
+    };
+
+    presses
+        .and_then(|presses| u64::try_from(presses).ok())
+        .ok_or(AnalysisError::Overflow)
+}
+
+/// Cycle of high pulses from each input of the conjunction feeding rx, read from the
+/// network's wiring where possible and simulated otherwise.
+pub fn find_cycles(input: &str) -> Result<HashMap<String, Cycle>, AnalysisError> {
+    let modules = parse_modules(input);
+    let final_name = final_conjunction(&modules)?;
+
+    match analyse_counters(&modules, final_name) {
+        Some(cycles) => Ok(cycles),
+        None => simulate_cycles(input, final_name),
+    }
+}
+
+/// Fewest button presses needed to deliver a low pulse to rx.
+///
+/// This assumes the final conjunction sees every input high at once on the first press
+/// where they all send a high pulse, as each input only drops low again at the end of it.
+pub fn presses_until_rx(input: &str) -> Result<u64, AnalysisError> {
+    let cycles: Vec<_> = find_cycles(input)?.into_values().collect();
+    combine_cycles(&cycles)
+}
+
+pub fn part2(input: &str) -> String {
+    presses_until_rx(input)
+        .expect("Invalid network")
+        .to_string()
 }
 
 #[cfg(test)]
@@ -246,6 +766,231 @@ broadcaster -> a
 &con -> output
 "#;
 
+    #[test]
+    fn test_to_dot() {
+        assert_eq!(
+            to_dot(TEST_INPUT_2, None),
+            r#"digraph modules {
+    button [shape=point];
+    "a" [shape=box, label="%a"];
+    "b" [shape=box, label="%b"];
+    "broadcaster" [shape=doublecircle, label="broadcaster"];
+    "con" [shape=invhouse, label="&con"];
+    "inv" [shape=invhouse, label="&inv"];
+    "output" [shape=plaintext];
+    button -> broadcaster;
+    "a" -> "inv";
+    "a" -> "con";
+    "b" -> "con";
+    "broadcaster" -> "a";
+    "con" -> "output";
+    "inv" -> "b";
+}
+"#
+        );
+
+        let dot = to_dot(TEST_INPUT_2, Some(1));
+        assert!(dot.contains(r#""a" [shape=box, label="%a\non"];"#));
+        assert!(dot.contains(r#""b" [shape=box, label="%b\non"];"#));
+        assert!(dot.contains(r#""a" -> "con" [label=high, color=red];"#));
+        assert!(dot.contains(r#""b" -> "con" [label=high, color=red];"#));
+        assert!(dot.contains(r#""a" -> "inv" [label=high, color=red];"#));
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut network = Network::parse(TEST_INPUT_1);
+        network.set_trace(true);
+        network.press_button().unwrap();
+
+        let trace: Vec<_> = network
+            .take_trace()
+            .into_iter()
+            .map(|event| (event.sender, event.receiver, event.pulse))
+            .collect();
+        assert_eq!(
+            trace,
+            vec![
+                ("button", "broadcaster", Pulse::Low),
+                ("broadcaster", "a", Pulse::Low),
+                ("broadcaster", "b", Pulse::Low),
+                ("broadcaster", "c", Pulse::Low),
+                ("a", "b", Pulse::High),
+                ("b", "c", Pulse::High),
+                ("c", "inv", Pulse::High),
+                ("inv", "a", Pulse::Low),
+                ("a", "b", Pulse::Low),
+                ("b", "c", Pulse::Low),
+                ("c", "inv", Pulse::Low),
+                ("inv", "a", Pulse::High),
+            ]
+        );
+        assert_eq!(network.counts(), PulseCounts { low: 8, high: 4 });
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        let mut network = Network::parse(TEST_INPUT_2);
+        network.watch("b", Pulse::Low);
+
+        // Stops partway through the press, with b yet to pass its pulse on
+        let hit = network.press_button().unwrap().unwrap();
+        assert_eq!((hit.sender, hit.receiver), ("inv", "b"));
+        assert!(!network.is_idle());
+        assert_eq!(network.press_button(), Err(PulsesInFlight));
+        assert_eq!(network.presses(), 1);
+        assert_eq!(network.run(), None);
+        assert!(network.is_idle());
+
+        // inv only sends b a low pulse on alternate presses
+        assert_eq!(network.press_button(), Ok(None));
+        assert!(network.press_button().unwrap().is_some());
+        assert_eq!(network.presses(), 3);
+    }
+
+    #[test]
+    fn test_snapshot_cycle() {
+        let mut network = Network::parse(TEST_INPUT_2);
+        let mut seen = HashMap::new();
+
+        while !seen.contains_key(&network.snapshot()) {
+            seen.insert(network.snapshot(), network.presses());
+            network.press_button().unwrap();
+        }
+
+        assert_eq!(seen[&network.snapshot()], 0);
+        assert_eq!(network.presses(), 4);
+    }
+
+    const COUNTERS_INPUT: &str = r#"
+broadcaster -> a0, b0
+%a0 -> a1, ahub
+%a1 -> a2
+%a2 -> ahub
+&ahub -> a0, a1, ainv
+&ainv -> final
+%b0 -> b1, bhub
+%b1 -> b2, bhub
+%b2 -> bhub
+&bhub -> b0, binv
+&binv -> final
+&final -> rx
+"#;
+
+    const FALLBACK_INPUT: &str = r#"
+broadcaster -> a, b
+%a -> x
+%b -> c
+%c -> y
+&x -> final
+&y -> final
+&final -> rx
+"#;
+
+    /// Presses until rx receives a low pulse, found by brute force.
+    fn simulate_rx(input: &str) -> usize {
+        let mut network = Network::parse(input);
+        network.watch("rx", Pulse::Low);
+        while network.press_button().unwrap().is_none() {}
+        network.presses()
+    }
+
+    #[test]
+    fn test_counter_analysis() {
+        let modules = parse_modules(COUNTERS_INPUT);
+        let cycles = analyse_counters(&modules, "final").unwrap();
+        assert_eq!(
+            cycles["ainv"],
+            Cycle {
+                first: 5,
+                period: 5
+            }
+        );
+        assert_eq!(
+            cycles["binv"],
+            Cycle {
+                first: 7,
+                period: 7
+            }
+        );
+
+        assert_eq!(simulate_cycles(COUNTERS_INPUT, "final").unwrap(), cycles);
+        assert_eq!(presses_until_rx(COUNTERS_INPUT), Ok(35));
+        assert_eq!(simulate_rx(COUNTERS_INPUT), 35);
+    }
+
+    #[test]
+    fn test_simulation_fallback() {
+        let modules = parse_modules(FALLBACK_INPUT);
+        assert_eq!(analyse_counters(&modules, "final"), None);
+
+        let cycles = find_cycles(FALLBACK_INPUT).unwrap();
+        assert_eq!(
+            cycles["x"],
+            Cycle {
+                first: 2,
+                period: 2
+            }
+        );
+        assert_eq!(
+            cycles["y"],
+            Cycle {
+                first: 4,
+                period: 4
+            }
+        );
+        assert_eq!(presses_until_rx(FALLBACK_INPUT), Ok(4));
+        assert_eq!(simulate_rx(FALLBACK_INPUT), 4);
+
+        assert_eq!(presses_until_rx(TEST_INPUT_2), Err(AnalysisError::NoRx));
+    }
+
+    #[test]
+    fn test_combine_cycles() {
+        let cycle = |first, period| Cycle { first, period };
+        assert_eq!(combine_cycles(&[cycle(3, 4), cycle(5, 6)]), Ok(11));
+        assert_eq!(combine_cycles(&[cycle(7, 4), cycle(11, 6)]), Ok(11));
+        assert_eq!(combine_cycles(&[cycle(15, 4), cycle(5, 6)]), Ok(23));
+        assert_eq!(
+            combine_cycles(&[cycle(3, 4), cycle(2, 6)]),
+            Err(AnalysisError::NeverSynchronises)
+        );
+        assert_eq!(
+            combine_cycles(&[cycle(3, 4), cycle(0, 0)]),
+            Err(AnalysisError::ZeroPeriod)
+        );
+
+        // Three coprime periods near 2^63 have a product too large for an i128
+        let large = [
+            cycle(1, (1 << 63) - 25),
+            cycle(2, (1 << 63) - 165),
+            cycle(3, (1 << 63) - 259),
+        ];
+        assert_eq!(combine_cycles(&large), Err(AnalysisError::Overflow));
+        let fits = [cycle(1, (1 << 31) - 1), cycle(2, 1 << 31)];
+        assert_eq!(combine_cycles(&fits), Ok((1 << 62) - (1 << 32) + 2));
+
+        // The moduli combine to just under 2^127, so rounding up to the start overflows
+        let late = [
+            cycle((1 << 63) + 1, (1 << 63) + 1),
+            cycle(u64::MAX - 1, u64::MAX - 1),
+        ];
+        assert_eq!(combine_cycles(&late), Err(AnalysisError::Overflow));
+    }
+
+    #[test]
+    fn test_irregular_cycle() {
+        // x only stays low on presses where a and b are both on, so its high pulses come
+        // on presses 1, 2, 4, 5, 6, 8...
+        let input = "broadcaster -> a\n%a -> x, b\n%b -> x\n&x -> final\n&final -> rx\n";
+        assert_eq!(
+            presses_until_rx(input),
+            Err(AnalysisError::IrregularCycle {
+                input: "x".to_string()
+            })
+        );
+    }
+
     #[test]
     fn test_part1() {
         assert_eq!(&part1(TEST_INPUT_1), "32000000");
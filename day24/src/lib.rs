@@ -1,124 +1,515 @@
-use nalgebra::{Matrix2, Matrix6, Vector2, Vector3, Vector6};
+use std::fmt;
 
-#[derive(Debug, PartialEq)]
-struct Line {
-    p: Vector3<f64>,
-    v: Vector3<f64>,
+use num_rational::Ratio;
+use num_traits::{CheckedAdd, CheckedMul, Zero};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    InvalidLine(String),
+    InvalidNumber(String),
+    WrongDimensions(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidLine(line) => write!(f, "invalid line: {:?}", line),
+            ParseError::InvalidNumber(number) => write!(f, "invalid number: {:?}", number),
+            ParseError::WrongDimensions(vector) => {
+                write!(f, "expected three coordinates: {:?}", vector)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// An intermediate value doesn't fit in an `i128`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overflow;
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "arithmetic overflow")
+    }
+}
+
+impl std::error::Error for Overflow {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RockError {
+    NoRock,
+    Overflow,
+}
+
+impl fmt::Display for RockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RockError::NoRock => write!(f, "no rock hits every hailstone"),
+            RockError::Overflow => write!(f, "arithmetic overflow"),
+        }
+    }
+}
+
+impl std::error::Error for RockError {}
+
+impl From<Overflow> for RockError {
+    fn from(_: Overflow) -> Self {
+        RockError::Overflow
+    }
+}
+
+type Vec3 = [i128; 3];
+
+fn sub(a: Vec3, b: Vec3) -> Result<Vec3, Overflow> {
+    let c = |i: usize| a[i].checked_sub(b[i]).ok_or(Overflow);
+    Ok([c(0)?, c(1)?, c(2)?])
+}
+
+fn add(a: Vec3, b: Vec3) -> Result<Vec3, Overflow> {
+    let c = |i: usize| a[i].checked_add(b[i]).ok_or(Overflow);
+    Ok([c(0)?, c(1)?, c(2)?])
+}
+
+fn scale(a: Vec3, t: i128) -> Result<Vec3, Overflow> {
+    let c = |i: usize| a[i].checked_mul(t).ok_or(Overflow);
+    Ok([c(0)?, c(1)?, c(2)?])
+}
+
+fn cross(a: Vec3, b: Vec3) -> Result<Vec3, Overflow> {
+    let term = |i: usize, j: usize| {
+        let (x, y) = (a[i].checked_mul(b[j]), a[j].checked_mul(b[i]));
+        x.zip(y).and_then(|(x, y)| x.checked_sub(y)).ok_or(Overflow)
+    };
+    Ok([term(1, 2)?, term(2, 0)?, term(0, 1)?])
+}
+
+fn gcd(a: i128, b: i128) -> Result<i128, Overflow> {
+    if b == 0 {
+        a.checked_abs().ok_or(Overflow)
+    } else {
+        gcd(b, a.checked_rem(b).ok_or(Overflow)?)
+    }
+}
+
+/// `a / b` if it divides exactly.
+fn exact_quotient(a: i128, b: i128) -> Result<Option<i128>, Overflow> {
+    if b == 0 {
+        return Ok(None);
+    }
+    if a.checked_rem(b).ok_or(Overflow)? != 0 {
+        return Ok(None);
+    }
+    a.checked_div(b).map(Some).ok_or(Overflow)
+}
+
+/// Divides every component by `divisor`, if they all divide exactly.
+fn exact_div(a: Vec3, divisor: i128) -> Result<Option<Vec3>, Overflow> {
+    let mut quotient = [0; 3];
+    for (q, &c) in quotient.iter_mut().zip(a.iter()) {
+        let Some(c) = exact_quotient(c, divisor)? else {
+            return Ok(None);
+        };
+        *q = c;
+    }
+    Ok(Some(quotient))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hailstone {
+    pub position: Vec3,
+    pub velocity: Vec3,
 }
 
-impl Line {
-    fn at(&self, t: f64) -> Vector3<f64> {
-        self.p + t * self.v
+impl Hailstone {
+    /// Time at which `self` and `other` occupy the same position, if ever. Returns 0 when
+    /// they travel together.
+    fn collision_time(&self, other: &Hailstone) -> Result<Option<i128>, Overflow> {
+        let dp = sub(other.position, self.position)?;
+        let dv = sub(self.velocity, other.velocity)?;
+
+        let Some(axis) = (0..3).find(|&axis| dv[axis] != 0) else {
+            return Ok((dp == [0; 3]).then_some(0));
+        };
+        let Some(t) = exact_quotient(dp[axis], dv[axis])? else {
+            return Ok(None);
+        };
+
+        Ok((scale(dv, t)? == dp).then_some(t))
     }
 }
 
-fn parse_lines(input: &str) -> Vec<Line> {
+/// Solves for the rock using hailstones `i` and `j`, viewed from `reference`.
+///
+/// Moving to the reference hailstone's frame leaves it stationary at the origin, so the
+/// rock's path must lie in the plane through the origin and each other hailstone's path.
+/// Crossing the planes' normals gives the direction exactly in integers.
+fn solve_triple(
+    reference: &Hailstone,
+    i: &Hailstone,
+    j: &Hailstone,
+) -> Result<Option<Hailstone>, Overflow> {
+    let relative = |h: &Hailstone| -> Result<Hailstone, Overflow> {
+        Ok(Hailstone {
+            position: sub(h.position, reference.position)?,
+            velocity: sub(h.velocity, reference.velocity)?,
+        })
+    };
+    let (i, j) = (relative(i)?, relative(j)?);
+
+    let direction = cross(
+        cross(i.position, i.velocity)?,
+        cross(j.position, j.velocity)?,
+    )?;
+    let divisor = direction.iter().try_fold(0, |acc, &c| gcd(acc, c))?;
+    let Some(direction) = exact_div(direction, divisor)? else {
+        return Ok(None);
+    };
+
+    // Time each hailstone crosses the rock's path through the origin
+    let hit = |h: &Hailstone| -> Result<Option<(i128, Vec3)>, Overflow> {
+        let p = cross(h.position, direction)?;
+        let v = cross(h.velocity, direction)?;
+        let Some(axis) = (0..3).find(|&axis| v[axis] != 0) else {
+            return Ok(None);
+        };
+        let Some(t) = exact_quotient(p[axis], v[axis])? else {
+            return Ok(None);
+        };
+        let t = t.checked_neg().ok_or(Overflow)?;
+        Ok(Some((t, add(h.position, scale(h.velocity, t)?)?)))
+    };
+    let (Some((ti, hi)), Some((tj, hj))) = (hit(&i)?, hit(&j)?) else {
+        return Ok(None);
+    };
+
+    let dt = tj.checked_sub(ti).ok_or(Overflow)?;
+    let Some(velocity) = exact_div(sub(hj, hi)?, dt)? else {
+        return Ok(None);
+    };
+    let position = sub(hi, scale(velocity, ti)?)?;
+
+    Ok(Some(Hailstone {
+        position: add(position, reference.position)?,
+        velocity: add(velocity, reference.velocity)?,
+    }))
+}
+
+/// Finds the rock that hits every hailstone at a non-negative integer time.
+///
+/// The first triple of hailstones that isn't degenerate fixes the only possible rock, which
+/// is then checked against the rest. Triples whose working overflows are skipped, and only
+/// reported if no other triple can be solved.
+pub fn find_rock(hailstones: &[Hailstone]) -> Result<Hailstone, RockError> {
+    let hits_all = |rock: &Hailstone| -> Result<bool, Overflow> {
+        for hailstone in hailstones {
+            if rock.collision_time(hailstone)?.is_none_or(|t| t < 0) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    };
+
+    let mut overflowed = false;
+    for (r, reference) in hailstones.iter().enumerate() {
+        for i in 0..hailstones.len() {
+            for j in i + 1..hailstones.len() {
+                if i == r || j == r {
+                    continue;
+                }
+                match solve_triple(reference, &hailstones[i], &hailstones[j]) {
+                    Ok(Some(rock)) => {
+                        return if hits_all(&rock)? {
+                            Ok(rock)
+                        } else {
+                            Err(RockError::NoRock)
+                        };
+                    }
+                    Ok(None) => {}
+                    // Another triple may still fit in an i128
+                    Err(Overflow) => overflowed = true,
+                }
+            }
+        }
+    }
+
+    Err(if overflowed {
+        RockError::Overflow
+    } else {
+        RockError::NoRock
+    })
+}
+
+fn parse_vec3(input: &str) -> Result<Vec3, ParseError> {
+    let coordinates: Vec<_> = input
+        .split(',')
+        .map(|c| {
+            c.trim()
+                .parse::<i128>()
+                .map_err(|_| ParseError::InvalidNumber(c.trim().to_string()))
+        })
+        .collect::<Result<_, _>>()?;
+
+    coordinates
+        .try_into()
+        .map_err(|_| ParseError::WrongDimensions(input.trim().to_string()))
+}
+
+fn parse_hailstones(input: &str) -> Result<Vec<Hailstone>, ParseError> {
     input
         .trim()
         .lines()
         .map(|line| {
-            let (p, v) = line.split_once('@').unwrap();
-            let mut parts = p.split(", ").map(|p| p.trim().parse().unwrap());
-            let p = Vector3::new(
-                parts.next().unwrap(),
-                parts.next().unwrap(),
-                parts.next().unwrap(),
-            );
-            let mut parts = v.split(", ").map(|v| v.trim().parse().unwrap());
-            let v = Vector3::new(
-                parts.next().unwrap(),
-                parts.next().unwrap(),
-                parts.next().unwrap(),
-            );
-            Line { p, v }
+            let (p, v) = line
+                .split_once('@')
+                .ok_or_else(|| ParseError::InvalidLine(line.to_string()))?;
+            Ok(Hailstone {
+                position: parse_vec3(p)?,
+                velocity: parse_vec3(v)?,
+            })
         })
         .collect()
 }
 
-fn intersection_xy(l1: &Line, l2: &Line) -> Option<Vector2<f64>> {
-    // Get parts
-    let p1 = l1.p.xy();
-    let v1 = l1.v.xy();
-    let p2 = l2.p.xy();
-    let v2 = l2.v.xy();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TestArea {
+    pub min: i128,
+    pub max: i128,
+}
+
+impl Default for TestArea {
+    fn default() -> Self {
+        Self {
+            min: 200000000000000,
+            max: 400000000000000,
+        }
+    }
+}
+
+type Point = [Ratio<i128>; 2];
 
-    // v1 - v2
-    let v1v2 = Matrix2::from_columns(&[v1, -v2]);
-    let v1v2_inverse = v1v2.try_inverse()?;
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Crossing {
+    pub hailstones: (usize, usize),
+    /// Where the paths cross, or the first shared point in the test area if they run
+    /// along the same line.
+    pub point: Point,
+    pub collinear: bool,
+}
+
+fn cross_xy(a: Vec3, b: Vec3) -> Result<i128, Overflow> {
+    let (x, y) = (a[0].checked_mul(b[1]), a[1].checked_mul(b[0]));
+    x.zip(y).and_then(|(x, y)| x.checked_sub(y)).ok_or(Overflow)
+}
+
+fn dot_xy(a: Vec3, b: Vec3) -> Result<i128, Overflow> {
+    let (x, y) = (a[0].checked_mul(b[0]), a[1].checked_mul(b[1]));
+    x.zip(y).and_then(|(x, y)| x.checked_add(y)).ok_or(Overflow)
+}
 
-    Some(v1v2_inverse * (p2 - p1))
+fn ratio(numerator: i128, denominator: i128) -> Result<Ratio<i128>, Overflow> {
+    // Ratio::new negates both parts of a negative denominator
+    if denominator < 0 {
+        let numerator = numerator.checked_neg().ok_or(Overflow)?;
+        let denominator = denominator.checked_neg().ok_or(Overflow)?;
+        return Ok(Ratio::new(numerator, denominator));
+    }
+    Ok(Ratio::new(numerator, denominator))
 }
 
-fn count_intersections(lines: &[Line], start: isize, stop: isize) -> usize {
-    let mut count = 0;
+/// Where the future paths of two hailstones cross inside `area`, ignoring the z axis.
+///
+/// Hailstones on the same line cross wherever their future paths overlap, so identical
+/// hailstones cross where they first enter the area.
+fn crossing_xy(
+    h1: &Hailstone,
+    h2: &Hailstone,
+    area: TestArea,
+) -> Result<Option<(Point, bool)>, Overflow> {
+    let (p1, v1) = (h1.position, h1.velocity);
+    let (p2, v2) = (h2.position, h2.velocity);
+    let d = sub(p2, p1)?;
+    let [min, max] = [area.min, area.max].map(Ratio::from);
+
+    let mut denominator = cross_xy(v1, v2)?;
+    if denominator != 0 {
+        // Solve p1 + t v1 = p2 + s v2, keeping the denominator positive to compare numerators
+        let mut t = cross_xy(d, v2)?;
+        let mut s = cross_xy(d, v1)?;
+        if denominator < 0 {
+            let neg = |x: i128| x.checked_neg().ok_or(Overflow);
+            (denominator, t, s) = (neg(denominator)?, neg(t)?, neg(s)?);
+        }
+        if t < 0 || s < 0 {
+            return Ok(None);
+        }
+
+        let numerator = |axis: usize| {
+            let start = p1[axis].checked_mul(denominator)?;
+            start.checked_add(t.checked_mul(v1[axis])?)
+        };
+        let point = [0, 1].map(|axis| numerator(axis).map(|c| Ratio::new_raw(c, denominator)));
+        let [Some(x), Some(y)] = point else {
+            return Err(Overflow);
+        };
 
-    // For every combination of lines
-    for i in 0..lines.len() {
-        for j in i + 1..lines.len() {
-            let l1 = &lines[i];
-            let l2 = &lines[j];
+        // Comparing ratios never overflows, and doesn't need them reduced first
+        let inside = [&x, &y].iter().all(|c| (min..=max).contains(*c));
+        return Ok(inside.then(|| ([x.reduced(), y.reduced()], false)));
+    }
+
+    // Parallel paths only meet if they're on the same line
+    if cross_xy(d, v1)? != 0 || cross_xy(d, v2)? != 0 {
+        return Ok(None);
+    }
+
+    // Measure along whichever path actually moves
+    let (p1, v1, p2, v2) = if v1[..2] != [0, 0] {
+        (p1, v1, p2, v2)
+    } else {
+        (p2, v2, p1, v1)
+    };
+    let d = sub(p2, p1)?;
+    if v1[..2] == [0, 0] {
+        let inside = (0..2).all(|axis| (area.min..=area.max).contains(&p1[axis]));
+        let point = [p1[0], p1[1]].map(Ratio::from);
+        return Ok((d[..2] == [0, 0] && inside).then_some((point, true)));
+    }
+
+    // Measure positions along the shared line as multiples of v1 from p1
+    let mut lower = vec![Ratio::zero()];
+    let mut upper = Vec::new();
+
+    let start2 = Ratio::new(dot_xy(d, v1)?, dot_xy(v1, v1)?);
+    match dot_xy(v1, v2)?.signum() {
+        1 => lower.push(start2),
+        -1 => upper.push(start2),
+        _ => {
+            lower.push(start2);
+            upper.push(start2);
+        }
+    }
 
-            // Same line
-            if l1 == l2 {
-                continue;
+    for axis in 0..2 {
+        if v1[axis] == 0 {
+            if !(area.min..=area.max).contains(&p1[axis]) {
+                return Ok(None);
             }
+            continue;
+        }
+        let offset = |bound: i128| bound.checked_sub(p1[axis]).ok_or(Overflow);
+        let a = ratio(offset(area.min)?, v1[axis])?;
+        let b = ratio(offset(area.max)?, v1[axis])?;
+        lower.push(a.min(b));
+        upper.push(a.max(b));
+    }
 
-            if let Some(t) = intersection_xy(l1, l2) {
-                // Find intersect point
-                let intersect = l1.at(t.x).xy();
+    let from = lower.into_iter().max().unwrap();
+    if upper.into_iter().any(|to| to < from) {
+        return Ok(None);
+    }
 
-                // Intersection must be in specified range
-                if intersect
-                    .iter()
-                    .any(|&c| c < start as f64 || c > stop as f64)
-                {
-                    continue;
-                }
+    let coordinate = |axis: usize| {
+        from.checked_mul(&Ratio::from(v1[axis]))
+            .and_then(|along| along.checked_add(&Ratio::from(p1[axis])))
+            .ok_or(Overflow)
+    };
+    Ok(Some(([coordinate(0)?, coordinate(1)?], true)))
+}
 
-                // Must occur in future
-                if t.iter().any(|&t| t < 0.0) {
-                    continue;
-                }
+pub fn find_crossings(hailstones: &[Hailstone], area: TestArea) -> Result<Vec<Crossing>, Overflow> {
+    let mut crossings = Vec::new();
 
-                count += 1;
+    // For every combination of hailstones
+    for i in 0..hailstones.len() {
+        for j in i + 1..hailstones.len() {
+            if let Some((point, collinear)) = crossing_xy(&hailstones[i], &hailstones[j], area)? {
+                crossings.push(Crossing {
+                    hailstones: (i, j),
+                    point,
+                    collinear,
+                });
             }
         }
     }
 
-    count
+    Ok(crossings)
+}
+
+fn count_intersections(hailstones: &[Hailstone], area: TestArea) -> Result<usize, Overflow> {
+    Ok(find_crossings(hailstones, area)?.len())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionError {
+    Unknown(String),
+    InvalidValue { key: String, value: String },
+    InvalidInput(ParseError),
+    Overflow,
 }
 
+impl fmt::Display for OptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptionError::Unknown(key) => write!(f, "unknown option {:?}", key),
+            OptionError::InvalidValue { key, value } => {
+                write!(f, "invalid value {:?} for option {:?}", value, key)
+            }
+            OptionError::InvalidInput(err) => write!(f, "{}", err),
+            OptionError::Overflow => write!(f, "arithmetic overflow"),
+        }
+    }
+}
+
+impl std::error::Error for OptionError {}
+
 pub fn part1(input: &str) -> String {
-    let lines = parse_lines(input);
-    count_intersections(&lines, 200000000000000, 400000000000000).to_string()
+    let hailstones = parse_hailstones(input).expect("Invalid hailstones");
+    count_intersections(&hailstones, TestArea::default())
+        .expect("Can't find crossings")
+        .to_string()
+}
+
+/// Part 1 with the test area given by `min` and `max`, listing each crossing as well if
+/// `points` is set.
+pub fn part1_with_options(input: &str, options: &[(&str, &str)]) -> Result<String, OptionError> {
+    let mut area = TestArea::default();
+    let mut report_points = false;
+
+    for &(key, value) in options {
+        let invalid = || OptionError::InvalidValue {
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+        match key {
+            "min" => area.min = value.parse().map_err(|_| invalid())?,
+            "max" => area.max = value.parse().map_err(|_| invalid())?,
+            "points" => report_points = value.parse().map_err(|_| invalid())?,
+            _ => return Err(OptionError::Unknown(key.to_string())),
+        }
+    }
+
+    let hailstones = parse_hailstones(input).map_err(OptionError::InvalidInput)?;
+    let crossings = find_crossings(&hailstones, area).map_err(|_| OptionError::Overflow)?;
+
+    let mut output = crossings.len().to_string();
+    if report_points {
+        for crossing in crossings {
+            let (i, j) = crossing.hailstones;
+            let [x, y] = crossing.point;
+            output += &format!("\n{} x {}: ({}, {})", i, j, x, y);
+            if crossing.collinear {
+                output += " collinear";
+            }
+        }
+    }
+    Ok(output)
 }
 
 pub fn part2(input: &str) -> String {
-    let lines = parse_lines(input);
-
-    // Given we're looking for a single line in three dimensions, we actually
-    // only need three points.
-    let [p1, v1] = [lines[0].p, lines[0].v];
-    let [p2, v2] = [lines[1].p, lines[1].v];
-    let [p3, v3] = [lines[2].p, lines[2].v];
-
-    // Create linear system of equations
-    let [l, r] = [
-        -p1.cross(&v1) + p2.cross(&v2),
-        -p1.cross(&v1) + p3.cross(&v3),
-    ];
-    let mut m = Matrix6::default();
-    m.view_mut((0, 0), (3, 3))
-        .copy_from_slice((v1.cross_matrix() - v2.cross_matrix()).as_slice());
-    m.view_mut((3, 0), (3, 3))
-        .copy_from_slice((v1.cross_matrix() - v3.cross_matrix()).as_slice());
-    m.view_mut((0, 3), (3, 3))
-        .copy_from_slice((-p1.cross_matrix() + p2.cross_matrix()).as_slice());
-    m.view_mut((3, 3), (3, 3))
-        .copy_from_slice((-p1.cross_matrix() + p3.cross_matrix()).as_slice());
-
-    let p = m.try_inverse().unwrap() * Vector6::new(l.x, l.y, l.z, r.x, r.y, r.z);
-    ((p.x + p.y + p.z).round() as usize).to_string()
+    let hailstones = parse_hailstones(input).expect("Invalid hailstones");
+    let rock = find_rock(&hailstones).expect("Can't find rock");
+    rock.position.iter().sum::<i128>().to_string()
 }
 
 #[cfg(test)]
@@ -135,12 +526,206 @@ mod tests {
 
     #[test]
     fn test_part1() {
-        let lines = parse_lines(TEST_INPUT);
-        assert_eq!(count_intersections(&lines, 7, 27), 2);
+        let hailstones = parse_hailstones(TEST_INPUT).unwrap();
+        assert_eq!(
+            count_intersections(&hailstones, TestArea { min: 7, max: 27 }),
+            Ok(2)
+        );
+    }
+
+    #[test]
+    fn test_crossing_points() {
+        let output = part1_with_options(
+            TEST_INPUT,
+            &[("min", "7"), ("max", "27"), ("points", "true")],
+        );
+        assert_eq!(
+            output.as_deref(),
+            Ok("2\n0 x 1: (43/3, 46/3)\n0 x 2: (35/3, 50/3)")
+        );
+        assert_eq!(
+            part1_with_options(TEST_INPUT, &[("size", "1")]),
+            Err(OptionError::Unknown("size".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_collinear() {
+        let area = TestArea { min: 0, max: 10 };
+        let hailstone = |position: Vec3, velocity: Vec3| Hailstone { position, velocity };
+
+        // Heading towards each other along the same line
+        let towards = [
+            hailstone([1, 1, 0], [1, 1, 0]),
+            hailstone([5, 5, 0], [-2, -2, 0]),
+        ];
+        // Heading apart
+        let apart = [
+            hailstone([1, 1, 0], [-1, -1, 0]),
+            hailstone([5, 5, 0], [2, 2, 0]),
+        ];
+        // Same direction, with the shared part starting outside the area
+        let outside = [
+            hailstone([20, 0, 0], [1, 0, 0]),
+            hailstone([12, 0, 0], [3, 0, 0]),
+        ];
+        // Parallel but on different lines
+        let parallel = [
+            hailstone([1, 1, 0], [1, 1, 0]),
+            hailstone([1, 2, 0], [1, 1, 0]),
+        ];
+
+        assert_eq!(
+            find_crossings(&towards, area),
+            Ok(vec![Crossing {
+                hailstones: (0, 1),
+                point: [Ratio::from(1), Ratio::from(1)],
+                collinear: true,
+            }])
+        );
+        assert_eq!(count_intersections(&apart, area), Ok(0));
+        assert_eq!(count_intersections(&outside, area), Ok(0));
+        assert_eq!(
+            count_intersections(&outside, TestArea { min: 0, max: 30 }),
+            Ok(1)
+        );
+        assert_eq!(count_intersections(&parallel, area), Ok(0));
+
+        // Identical hailstones share their whole future path, so they cross where they
+        // enter the area
+        let identical = [
+            hailstone([-4, 2, 0], [2, 1, 0]),
+            hailstone([-4, 2, 0], [2, 1, 0]),
+        ];
+        assert_eq!(
+            find_crossings(&identical, area),
+            Ok(vec![Crossing {
+                hailstones: (0, 1),
+                point: [Ratio::from(0), Ratio::from(4)],
+                collinear: true,
+            }])
+        );
+        let stationary = [
+            hailstone([3, 3, 0], [0, 0, 0]),
+            hailstone([3, 3, 0], [0, 0, 0]),
+        ];
+        assert_eq!(count_intersections(&stationary, area), Ok(1));
+    }
+
+    #[test]
+    fn test_overflow() {
+        // Bounds far outside the coordinates are compared without scaling them
+        let hailstones = parse_hailstones(TEST_INPUT).unwrap();
+        let everywhere = TestArea {
+            min: i128::MIN,
+            max: i128::MAX,
+        };
+        assert_eq!(count_intersections(&hailstones, everywhere), Ok(5));
+
+        let hailstone = |position: Vec3, velocity: Vec3| Hailstone { position, velocity };
+        let huge = [
+            hailstone([i128::MAX / 2, 0, 0], [-3, 1, 0]),
+            hailstone([0, 0, 0], [1, 2, 0]),
+        ];
+        assert_eq!(
+            count_intersections(&huge, TestArea::default()),
+            Err(Overflow)
+        );
+        assert_eq!(
+            part1_with_options(
+                "0, 0, 0 @ 1, 2, 0\n0, 0, 0 @ 3, 1, 0",
+                &[("min", "0"), ("max", &i128::MAX.to_string())]
+            ),
+            Ok("1".to_string())
+        );
+        assert_eq!(
+            part1_with_options(
+                &format!("{}, 0, 0 @ -3, 1, 0\n0, 0, 0 @ 1, 2, 0", i128::MAX / 2),
+                &[]
+            ),
+            Err(OptionError::Overflow)
+        );
     }
 
     #[test]
     fn test_part2() {
         assert_eq!(&part2(TEST_INPUT), "47");
     }
+
+    #[test]
+    fn test_find_rock() {
+        let rock = find_rock(&parse_hailstones(TEST_INPUT).unwrap()).unwrap();
+        assert_eq!(rock.position, [24, 13, 10]);
+        assert_eq!(rock.velocity, [-3, 1, 2]);
+
+        // First hailstones repeated, so the leading triples are degenerate
+        let mut hailstones = parse_hailstones(TEST_INPUT).unwrap();
+        hailstones.insert(0, hailstones[0]);
+        hailstones.insert(0, hailstones[0]);
+        assert_eq!(find_rock(&hailstones), Ok(rock));
+
+        // A hailstone the rock can't reach
+        hailstones.push(Hailstone {
+            position: [0, 0, 0],
+            velocity: [1, 1, 1],
+        });
+        assert_eq!(find_rock(&hailstones), Err(RockError::NoRock));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            parse_hailstones("1, 2, 3 @ 4, 5"),
+            Err(ParseError::WrongDimensions("4, 5".to_string()))
+        );
+        assert_eq!(
+            parse_hailstones("1, 2, x @ 4, 5, 6"),
+            Err(ParseError::InvalidNumber("x".to_string()))
+        );
+        assert_eq!(
+            parse_hailstones("1, 2, 3, 4, 5, 6"),
+            Err(ParseError::InvalidLine("1, 2, 3, 4, 5, 6".to_string()))
+        );
+        assert_eq!(
+            part1_with_options("1, 2, 3", &[]),
+            Err(OptionError::InvalidInput(ParseError::InvalidLine(
+                "1, 2, 3".to_string()
+            )))
+        );
+
+        // Relative positions no longer fit in an i128
+        let hailstone = |position: Vec3, velocity: Vec3| Hailstone { position, velocity };
+        let hailstones = [
+            hailstone([i128::MIN, 0, 0], [1, 0, 0]),
+            hailstone([i128::MAX, 0, 0], [0, 1, 0]),
+            hailstone([0, 0, 0], [0, 0, 1]),
+        ];
+        assert_eq!(find_rock(&hailstones), Err(RockError::Overflow));
+    }
+
+    #[test]
+    fn test_large_coordinates() {
+        let rock = Hailstone {
+            position: [229429688799267, 217160931330282, 122181033382403],
+            velocity: [-131, -72, 55],
+        };
+        let hailstones: Vec<_> = [
+            ([-12, 45, 8], 540129744),
+            ([201, -3, -77], 910452318),
+            ([-5, 17, 33], 177218490),
+            ([64, -98, 12], 301987651),
+        ]
+        .into_iter()
+        .map(|(velocity, t): (Vec3, i128)| Hailstone {
+            position: sub(
+                add(rock.position, scale(rock.velocity, t).unwrap()).unwrap(),
+                scale(velocity, t).unwrap(),
+            )
+            .unwrap(),
+            velocity,
+        })
+        .collect();
+
+        assert_eq!(find_rock(&hailstones), Ok(rock));
+    }
 }
@@ -1,173 +1,430 @@
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
-
-#[derive(Debug, PartialEq, Eq)]
-struct MapRange {
-    destination: u32,
-    source: u32,
-    len: u32,
+use std::{fmt, num::ParseIntError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    MissingMaps,
+    InvalidNumber(ParseIntError),
+    InvalidRange(String),
+    OddSeedRanges,
+    Overflow { start: u64, len: u64 },
+    Overlap { source: u64 },
 }
 
-impl MapRange {
-    fn contains_source(&self, x: u32) -> bool {
-        x >= self.source && x < (self.source + self.len)
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::MissingMaps => write!(f, "almanac has no maps"),
+            ParseError::InvalidNumber(err) => write!(f, "invalid number: {}", err),
+            ParseError::InvalidRange(line) => write!(f, "invalid range: {:?}", line),
+            ParseError::OddSeedRanges => write!(f, "seed ranges must come in pairs"),
+            ParseError::Overflow { start, len } => {
+                write!(f, "range of {} starting at {} overflows", len, start)
+            }
+            ParseError::Overlap { source } => {
+                write!(f, "map ranges overlap at source {}", source)
+            }
+        }
     }
 }
 
-type Map = Vec<MapRange>;
+impl std::error::Error for ParseError {}
 
-#[derive(Debug, PartialEq, Eq)]
-struct InputRange {
-    start: u32,
-    len: u32,
+impl From<ParseIntError> for ParseError {
+    fn from(err: ParseIntError) -> Self {
+        ParseError::InvalidNumber(err)
+    }
 }
 
-fn parse_seeds(input: &str) -> Vec<u32> {
-    input
-        .split_whitespace()
-        .skip(1)
-        .map(|seed| seed.parse::<u32>())
-        .collect::<Result<Vec<_>, _>>()
-        .unwrap()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MapRange {
+    destination: u64,
+    source: u64,
+    len: u64,
 }
 
-fn parse_seed_ranges(input: &str) -> Vec<InputRange> {
-    let parts = input
-        .split_whitespace()
-        .skip(1)
-        .map(|seed| seed.parse::<u32>())
-        .collect::<Result<Vec<_>, _>>()
-        .unwrap();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputRange {
+    pub start: u64,
+    pub len: u64,
+}
+
+impl InputRange {
+    fn last(&self) -> u64 {
+        self.start + (self.len - 1)
+    }
 
-    parts
-        .chunks(2)
-        .map(|chunk| InputRange {
-            start: chunk[0],
-            len: chunk[1],
+    fn intersect(&self, other: &InputRange) -> Option<InputRange> {
+        let start = self.start.max(other.start);
+        let last = self.last().min(other.last());
+        (start <= last).then(|| InputRange {
+            start,
+            len: last - start + 1,
         })
-        .collect()
+    }
 }
 
-fn parse_range(input: &str) -> MapRange {
-    let mut parts = input.split_whitespace();
-    let mut next_part = || parts.next().unwrap().parse::<u32>().unwrap();
+/// Sorts ranges and joins any that overlap or touch.
+fn coalesce(mut ranges: Vec<InputRange>) -> Vec<InputRange> {
+    ranges.sort_unstable_by_key(|range| range.start);
+
+    let mut coalesced: Vec<InputRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        if let Some(last) = coalesced.last_mut() {
+            if last
+                .last()
+                .checked_add(1)
+                .is_none_or(|next| range.start <= next)
+            {
+                let end = last.last().max(range.last());
+                last.len = end - last.start + 1;
+                continue;
+            }
+        }
+        coalesced.push(range);
+    }
+    coalesced
+}
 
-    let destination = next_part();
-    let source = next_part();
-    let len = next_part();
+/// The inclusive `start..=end` of an interval map, shifted to begin at `destination`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Piece {
+    start: u64,
+    end: u64,
+    destination: u64,
+}
 
-    MapRange {
-        destination,
-        source,
-        len,
+impl Piece {
+    fn map(&self, x: u64) -> u64 {
+        self.destination + (x - self.start)
+    }
+
+    fn destination_end(&self) -> u64 {
+        self.destination + (self.end - self.start)
     }
 }
 
-fn parse_maps(input: &str) -> Vec<Map> {
-    input
-        .trim()
-        .split("\n\n")
-        .map(|map| map.lines().skip(1).map(parse_range).collect::<Map>())
-        .collect()
+/// A piecewise shift covering every `u64`, kept sorted with no gaps or overlaps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IntervalMap {
+    pieces: Vec<Piece>,
 }
 
-fn map_input(input: u32, map: &Map) -> u32 {
-    for range in map {
-        // If input is in range
-        if range.contains_source(input) {
-            let offset = input - range.source;
-            return range.destination + offset;
+impl IntervalMap {
+    fn identity() -> Self {
+        Self {
+            pieces: vec![Piece {
+                start: 0,
+                end: u64::MAX,
+                destination: 0,
+            }],
         }
     }
 
-    // Unmapped correspond to the same destination number
-    input
-}
+    fn from_ranges(mut ranges: Vec<MapRange>) -> Result<Self, ParseError> {
+        ranges.retain(|range| range.len > 0);
+        ranges.sort_unstable_by_key(|range| range.source);
+
+        let mut pieces = Vec::with_capacity(2 * ranges.len() + 1);
+        let mut next_start = Some(0);
+
+        for range in ranges {
+            let overflow = |start| ParseError::Overflow {
+                start,
+                len: range.len,
+            };
+            let end = range
+                .source
+                .checked_add(range.len - 1)
+                .ok_or(overflow(range.source))?;
+            range
+                .destination
+                .checked_add(range.len - 1)
+                .ok_or(overflow(range.destination))?;
+
+            // Unmapped values correspond to the same destination number
+            match next_start {
+                Some(start) if start < range.source => pieces.push(Piece {
+                    start,
+                    end: range.source - 1,
+                    destination: start,
+                }),
+                Some(start) if start == range.source => {}
+                _ => {
+                    return Err(ParseError::Overlap {
+                        source: range.source,
+                    })
+                }
+            }
 
-fn split_input_range(mut input_range: InputRange, map: &Map) -> Vec<InputRange> {
-    // All the points where the input range needs to be split
-    let split_points: Vec<u32> = map
-        .iter()
-        .flat_map(|range| [range.source, range.source + range.len])
-        .collect();
+            pieces.push(Piece {
+                start: range.source,
+                end,
+                destination: range.destination,
+            });
+            next_start = end.checked_add(1);
+        }
+
+        if let Some(start) = next_start {
+            pieces.push(Piece {
+                start,
+                end: u64::MAX,
+                destination: start,
+            });
+        }
+
+        Ok(Self { pieces })
+    }
+
+    /// Index of the piece containing `x`.
+    fn find(&self, x: u64) -> usize {
+        self.pieces.partition_point(|piece| piece.end < x)
+    }
 
-    let mut output_ranges = Vec::new();
+    fn map(&self, x: u64) -> u64 {
+        self.pieces[self.find(x)].map(x)
+    }
 
-    while input_range.len > 0 {
-        // Find the next smallest split range inside input range
-        let next_split = split_points
+    /// Pieces overlapping `start..=end`, clipped to it.
+    fn clipped(&self, start: u64, end: u64) -> impl Iterator<Item = Piece> + '_ {
+        self.pieces[self.find(start)..]
             .iter()
-            .filter(|&&split| {
-                input_range.start < split && (input_range.start + input_range.len) >= split
+            .take_while(move |piece| piece.start <= end)
+            .map(move |piece| {
+                let clipped_start = piece.start.max(start);
+                Piece {
+                    start: clipped_start,
+                    end: piece.end.min(end),
+                    destination: piece.map(clipped_start),
+                }
             })
-            .min();
+    }
 
-        if let Some(next_split) = next_split {
-            let input_range_end = input_range.start + input_range.len - 1;
+    fn map_range(&self, range: InputRange) -> Vec<InputRange> {
+        self.clipped(range.start, range.last())
+            .map(|piece| InputRange {
+                start: piece.destination,
+                len: piece.end - piece.start + 1,
+            })
+            .collect()
+    }
 
-            // We're done
-            if *next_split > input_range_end {
-                output_ranges.push(input_range);
-                break;
-            }
+    /// Smallest value produced by any input within `range`.
+    fn min_in_range(&self, range: InputRange) -> u64 {
+        self.map_range(range)
+            .into_iter()
+            .map(|range| range.start)
+            .min()
+            .unwrap()
+    }
 
-            output_ranges.push(InputRange {
-                start: input_range.start,
-                len: next_split - input_range.start,
-            });
+    /// Every input range that maps into `range`.
+    fn preimage(&self, range: InputRange) -> Vec<InputRange> {
+        let ranges = self
+            .pieces
+            .iter()
+            .filter_map(|piece| {
+                let image = InputRange {
+                    start: piece.destination,
+                    len: piece.end - piece.start + 1,
+                };
+                image.intersect(&range).map(|overlap| InputRange {
+                    start: piece.start + (overlap.start - piece.destination),
+                    len: overlap.len,
+                })
+            })
+            .collect();
+        coalesce(ranges)
+    }
 
-            input_range.len -= next_split - input_range.start;
-            input_range.start = *next_split;
-        } else {
-            output_ranges.push(input_range);
-            break;
+    /// Applies `self` followed by `next` as a single map.
+    fn then(&self, next: &IntervalMap) -> IntervalMap {
+        let mut pieces: Vec<Piece> = Vec::with_capacity(self.pieces.len() + next.pieces.len());
+
+        for piece in self.pieces.iter() {
+            for part in next.clipped(piece.destination, piece.destination_end()) {
+                let start = piece.start + (part.start - piece.destination);
+                let end = piece.start + (part.end - piece.destination);
+
+                // Merge with the previous piece if it continues the same shift
+                if let Some(last) = pieces.last_mut() {
+                    if last.destination_end().checked_add(1) == Some(part.destination) {
+                        last.end = end;
+                        continue;
+                    }
+                }
+
+                pieces.push(Piece {
+                    start,
+                    end,
+                    destination: part.destination,
+                });
+            }
         }
+
+        IntervalMap { pieces }
     }
+}
 
-    output_ranges
+pub struct Almanac {
+    seeds: Vec<u64>,
+    maps: Vec<IntervalMap>,
 }
 
-fn map_input_range(input_range: InputRange, map: &Map) -> Vec<InputRange> {
-    let mut input_ranges = split_input_range(input_range, map);
+impl Almanac {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let (seeds, maps) = input
+            .trim()
+            .split_once("\n\n")
+            .ok_or(ParseError::MissingMaps)?;
+
+        let seeds = seeds
+            .split_whitespace()
+            .skip(1)
+            .map(|seed| seed.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let maps = maps
+            .split("\n\n")
+            .map(|map| {
+                let ranges = map
+                    .lines()
+                    .skip(1)
+                    .map(parse_range)
+                    .collect::<Result<Vec<_>, _>>()?;
+                IntervalMap::from_ranges(ranges)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
-    for range in input_ranges.iter_mut() {
-        range.start = map_input(range.start, map);
+        Ok(Self { seeds, maps })
     }
-    input_ranges
-}
 
-pub fn part1(input: &str) -> String {
-    // Parse input
-    let (seeds, maps) = input.split_once("\n\n").unwrap();
-    let mut inputs = parse_seeds(seeds);
-    let maps = parse_maps(maps);
-
-    for map in maps {
-        for input in inputs.iter_mut() {
-            *input = map_input(*input, &map);
+    pub fn seed_ranges(&self) -> Result<Vec<InputRange>, ParseError> {
+        if !self.seeds.len().is_multiple_of(2) {
+            return Err(ParseError::OddSeedRanges);
         }
+
+        let mut ranges = Vec::new();
+        for chunk in self.seeds.chunks(2) {
+            let range = InputRange {
+                start: chunk[0],
+                len: chunk[1],
+            };
+            if range.len == 0 {
+                continue;
+            }
+            if range.start.checked_add(range.len - 1).is_none() {
+                return Err(ParseError::Overflow {
+                    start: range.start,
+                    len: range.len,
+                });
+            }
+            ranges.push(range);
+        }
+        Ok(ranges)
     }
 
-    inputs.into_iter().min().unwrap().to_string()
-}
+    pub fn location(&self, seed: u64) -> u64 {
+        self.maps.iter().fold(seed, |x, map| map.map(x))
+    }
 
-pub fn part2(input: &str) -> String {
-    // Parse input
-    let (seeds, maps) = input.split_once("\n\n").unwrap();
-    let mut input_ranges = parse_seed_ranges(seeds);
-    let maps = parse_maps(maps);
-
-    for map in maps {
-        let output_ranges: Vec<_> = input_ranges
-            .into_par_iter()
-            .flat_map(|input_range| map_input_range(input_range, &map))
-            .collect();
+    /// Walks the maps backwards to find every seed range ending up in `locations`.
+    pub fn seeds_for_locations(&self, locations: InputRange) -> Vec<InputRange> {
+        self.maps.iter().rev().fold(vec![locations], |ranges, map| {
+            coalesce(
+                ranges
+                    .into_iter()
+                    .flat_map(|range| map.preimage(range))
+                    .collect(),
+            )
+        })
+    }
+
+    pub fn seeds_for_location(&self, location: u64) -> Vec<u64> {
+        self.seeds_for_locations(InputRange {
+            start: location,
+            len: 1,
+        })
+        .into_iter()
+        .flat_map(|range| range.start..=range.last())
+        .collect()
+    }
+
+    /// Lowest location reached from `seed_ranges`, found by searching upwards through
+    /// ever larger blocks of locations until one leads back to a seed.
+    pub fn lowest_location_by_search(&self, seed_ranges: &[InputRange]) -> Option<u64> {
+        let seed_to_location = self.seed_to_location();
+        let mut block = InputRange { start: 0, len: 1 };
+
+        loop {
+            let lowest = self
+                .seeds_for_locations(block)
+                .iter()
+                .flat_map(|seeds| {
+                    seed_ranges
+                        .iter()
+                        .filter_map(|range| range.intersect(seeds))
+                })
+                .flat_map(|seeds| seed_to_location.map_range(seeds))
+                .filter_map(|locations| locations.intersect(&block))
+                .map(|locations| locations.start)
+                .min();
+            if lowest.is_some() {
+                return lowest;
+            }
 
-        input_ranges = output_ranges;
+            let start = block.start.checked_add(block.len)?;
+            let len = block.len.saturating_mul(2).min(u64::MAX - start + 1);
+            block = InputRange { start, len };
+        }
+    }
+
+    /// All maps composed into a single seed to location map.
+    fn seed_to_location(&self) -> IntervalMap {
+        self.maps
+            .iter()
+            .fold(IntervalMap::identity(), |map, next| map.then(next))
     }
+}
+
+fn parse_range(input: &str) -> Result<MapRange, ParseError> {
+    let parts = input
+        .split_whitespace()
+        .map(|part| part.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let [destination, source, len] = parts[..] else {
+        return Err(ParseError::InvalidRange(input.to_string()));
+    };
+
+    Ok(MapRange {
+        destination,
+        source,
+        len,
+    })
+}
+
+pub fn part1(input: &str) -> String {
+    let almanac = Almanac::parse(input).expect("Invalid almanac");
+    let seed_to_location = almanac.seed_to_location();
 
-    input_ranges
+    almanac
+        .seeds
+        .iter()
+        .map(|&seed| seed_to_location.map(seed))
+        .min()
+        .unwrap()
+        .to_string()
+}
+
+pub fn part2(input: &str) -> String {
+    let almanac = Almanac::parse(input).expect("Invalid almanac");
+    let seed_to_location = almanac.seed_to_location();
+
+    almanac
+        .seed_ranges()
+        .expect("Invalid seed ranges")
         .into_iter()
-        .map(|range| range.start)
+        .map(|range| seed_to_location.min_in_range(range))
         .min()
         .unwrap()
         .to_string()
@@ -227,23 +484,24 @@ humidity-to-location map:
     fn test_split_range() {
         // Test with one range inside input
         let input_range = InputRange { start: 1, len: 6 };
-        let map = vec![MapRange {
+        let map = IntervalMap::from_ranges(vec![MapRange {
             destination: 0,
             source: 3,
             len: 3,
-        }];
+        }])
+        .unwrap();
 
         let expected_output = vec![
             InputRange { start: 1, len: 2 },
-            InputRange { start: 3, len: 3 },
+            InputRange { start: 0, len: 3 },
             InputRange { start: 6, len: 1 },
         ];
-        let actual_output = split_input_range(input_range, &map);
+        let actual_output = map.map_range(input_range);
         assert_eq!(actual_output, expected_output);
 
         // Test with two ranges overlapping input start and end
         let input_range = InputRange { start: 1, len: 4 };
-        let map = vec![
+        let map = IntervalMap::from_ranges(vec![
             MapRange {
                 destination: 0,
                 source: 0,
@@ -254,20 +512,21 @@ humidity-to-location map:
                 source: 4,
                 len: 2,
             },
-        ];
+        ])
+        .unwrap();
         let expected_output = vec![
             InputRange { start: 1, len: 1 },
             InputRange { start: 2, len: 2 },
-            InputRange { start: 4, len: 1 },
+            InputRange { start: 0, len: 1 },
         ];
-        let actual_output = split_input_range(input_range, &map);
+        let actual_output = map.map_range(input_range);
         assert_eq!(actual_output, expected_output);
     }
 
     #[test]
     fn test_map_range() {
         let input_range = InputRange { start: 10, len: 20 };
-        let map = vec![
+        let map = IntervalMap::from_ranges(vec![
             MapRange {
                 destination: 100,
                 source: 10,
@@ -278,7 +537,8 @@ humidity-to-location map:
                 source: 20,
                 len: 10,
             },
-        ];
+        ])
+        .unwrap();
 
         let expected_output = vec![
             InputRange {
@@ -290,7 +550,86 @@ humidity-to-location map:
                 len: 10,
             },
         ];
-        let actual_output = map_input_range(input_range, &map);
+        let actual_output = map.map_range(input_range);
         assert_eq!(actual_output, expected_output);
     }
+
+    #[test]
+    fn test_compose_matches_steps() {
+        let almanac = Almanac::parse(TEST_INPUT).unwrap();
+        let seed_to_location = almanac.seed_to_location();
+
+        for seed in 0..120 {
+            let stepped = almanac.maps.iter().fold(seed, |x, map| map.map(x));
+            assert_eq!(seed_to_location.map(seed), stepped);
+        }
+    }
+
+    #[test]
+    fn test_reverse_lookup() {
+        let almanac = Almanac::parse(TEST_INPUT).unwrap();
+
+        assert_eq!(almanac.seeds_for_location(82), vec![79]);
+        assert_eq!(almanac.seeds_for_location(35), vec![13]);
+        assert_eq!(almanac.seeds_for_location(46), vec![82]);
+
+        for seed in 0..120 {
+            let location = almanac.location(seed);
+            assert!(almanac.seeds_for_location(location).contains(&seed));
+        }
+
+        let seeds = almanac.seeds_for_locations(InputRange { start: 0, len: 100 });
+        for seed in 0..120 {
+            let found = seeds.iter().any(|range| {
+                range
+                    .intersect(&InputRange {
+                        start: seed,
+                        len: 1,
+                    })
+                    .is_some()
+            });
+            assert_eq!(found, almanac.location(seed) < 100);
+        }
+    }
+
+    #[test]
+    fn test_search_matches_part2() {
+        let almanac = Almanac::parse(TEST_INPUT).unwrap();
+        let seed_ranges = almanac.seed_ranges().unwrap();
+        assert_eq!(almanac.lowest_location_by_search(&seed_ranges), Some(46));
+    }
+
+    #[test]
+    fn test_large_values() {
+        let input = format!(
+            "seeds: 8 3 {} 1\n\nseed-to-soil map:\n0 {} 10\n{} 0 10\n",
+            u64::MAX,
+            u64::MAX - 9,
+            u64::MAX - 9,
+        );
+        assert_eq!(&part1(&input), "9");
+        assert_eq!(&part2(&input), "9");
+
+        let overflowing = format!("seeds: 1\n\nseed-to-soil map:\n0 {} 11\n", u64::MAX - 9);
+        assert_eq!(
+            Almanac::parse(&overflowing).err(),
+            Some(ParseError::Overflow {
+                start: u64::MAX - 9,
+                len: 11
+            })
+        );
+        // Both ends overflow, and the source is checked first
+        let overflowing = format!(
+            "seeds: 1\n\nseed-to-soil map:\n{} {} 11\n",
+            u64::MAX - 1,
+            u64::MAX - 3
+        );
+        assert_eq!(
+            Almanac::parse(&overflowing).err(),
+            Some(ParseError::Overflow {
+                start: u64::MAX - 3,
+                len: 11
+            })
+        );
+    }
 }
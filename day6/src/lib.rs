@@ -1,7 +1,7 @@
-#[derive(Debug)]
-struct Race {
-    time: u64,
-    distance: u64,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Race {
+    pub time: u64,
+    pub distance: u64,
 }
 
 fn parse_races(input: &str) -> Vec<Race> {
@@ -29,43 +29,38 @@ fn parse_single_race(input: &str) -> Race {
     }
 }
 
-fn satisfies_constraints(race: &Race, charge_time: u64) -> bool {
-    // Solved using inequalites
-    // charge_time <= race_time
-    // charge_time + distance / charge_time <= race_time
-    (charge_time * race.time) > (race.distance + charge_time.pow(2))
-}
-
-fn find_start_end_values(race: &Race) -> (u64, u64) {
-    // Binary search for first satisfied constraint
-    let mut low = 1;
-    let mut high = race.time;
-
-    while low < high {
-        let mid = low + (high - low) / 2;
-        if satisfies_constraints(race, mid) {
-            high = mid;
-        } else {
-            low = mid + 1;
+impl Race {
+    /// First and last charge times that beat the record distance, if any.
+    ///
+    /// Charging for `t` travels `t * (time - t)`, which beats the record when
+    /// `(time - 2t)^2 < time^2 - 4 * distance`. Since `time - 2t` always has the same parity
+    /// as `time`, the winning times are those where it lies in the widest interval
+    /// `[-u, u]` of that parity inside the square root of the discriminant.
+    pub fn winning_range(&self) -> Option<(u64, u64)> {
+        let time = self.time as u128;
+        let discriminant = (time * time).checked_sub(4 * self.distance as u128)?;
+        if discriminant == 0 {
+            return None;
         }
-    }
-    let start = low;
 
-    // Reset and perform search for last satisfied constraint
-    low = start;
-    high = race.time;
-
-    while low < high {
-        let mid = low + (high - low) / 2;
-        if satisfies_constraints(race, mid) {
-            low = mid + 1;
+        // Largest u with u^2 strictly below the discriminant, so ties don't win
+        let root = discriminant.isqrt();
+        let mut u = if root * root == discriminant {
+            root - 1
         } else {
-            high = mid;
+            root
+        };
+        if (time - u) % 2 == 1 {
+            u = u.checked_sub(1)?;
         }
+
+        Some((((time - u) / 2) as u64, ((time + u) / 2) as u64))
     }
-    let end = low - 1;
 
-    (start, end)
+    pub fn ways_to_win(&self) -> u64 {
+        self.winning_range()
+            .map_or(0, |(start, end)| end - start + 1)
+    }
 }
 
 pub fn part1(input: &str) -> String {
@@ -73,10 +68,7 @@ pub fn part1(input: &str) -> String {
 
     races
         .into_iter()
-        .map(|race| {
-            let (start, end) = find_start_end_values(&race);
-            (end - start) + 1
-        })
+        .map(|race| race.ways_to_win())
         .product::<u64>()
         .to_string()
 }
@@ -84,8 +76,7 @@ pub fn part1(input: &str) -> String {
 pub fn part2(input: &str) -> String {
     let race = parse_single_race(input);
 
-    let (start, end) = find_start_end_values(&race);
-    ((end - start) + 1).to_string()
+    race.ways_to_win().to_string()
 }
 
 #[cfg(test)]
@@ -97,6 +88,88 @@ Time:      7  15   30
 Distance:  9  40  200
 "#;
 
+    fn brute_force(race: &Race) -> Option<(u64, u64)> {
+        let wins = |t: &u64| t * (race.time - t) > race.distance;
+        let start = (0..=race.time).find(wins)?;
+        let end = (0..=race.time).rev().find(wins)?;
+        Some((start, end))
+    }
+
+    #[test]
+    fn test_brute_force() {
+        for time in 0..60 {
+            for distance in 0..=time * time / 4 + 2 {
+                let race = Race { time, distance };
+                assert_eq!(race.winning_range(), brute_force(&race), "{:?}", race);
+            }
+        }
+    }
+
+    #[test]
+    fn test_edge_cases() {
+        // Charging for 3 ties the record exactly
+        assert_eq!(
+            Race {
+                time: 6,
+                distance: 9
+            }
+            .winning_range(),
+            None
+        );
+        assert_eq!(
+            Race {
+                time: 6,
+                distance: 8
+            }
+            .winning_range(),
+            Some((3, 3))
+        );
+        assert_eq!(
+            Race {
+                time: 30,
+                distance: 200
+            }
+            .winning_range(),
+            Some((11, 19))
+        );
+        assert_eq!(
+            Race {
+                time: 0,
+                distance: 0
+            }
+            .ways_to_win(),
+            0
+        );
+        assert_eq!(
+            Race {
+                time: 1,
+                distance: 0
+            }
+            .ways_to_win(),
+            0
+        );
+        assert_eq!(
+            Race {
+                time: 2,
+                distance: 0
+            }
+            .ways_to_win(),
+            1
+        );
+
+        // Products overflow u64 without wider arithmetic
+        let race = Race {
+            time: u64::MAX,
+            distance: 0,
+        };
+        assert_eq!(race.winning_range(), Some((1, u64::MAX - 1)));
+        let race = Race {
+            time: u64::MAX,
+            distance: u64::MAX,
+        };
+        assert_eq!(race.winning_range(), Some((2, u64::MAX - 2)));
+    }
+
     #[test]
     fn test_part1() {
         assert_eq!(&part1(TEST_INPUT), "288");
@@ -1,153 +1,237 @@
-use std::cmp::Ordering;
-
-const CARDS: [char; 14] = [
-    'J', '2', '3', '4', '5', '6', '7', '8', '9', 'T', 'J', 'Q', 'K', 'A',
-];
-
-#[derive(Debug, Eq)]
-struct Hand {
-    cards: [char; 5],
-    joker: bool,
-    bid: u32,
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameError {
+    NoCards,
+    DuplicateCard(char),
+    NonAsciiCard(char),
+    UnknownWild(char),
+    /// Hands of this size can't be packed into a sort key.
+    HandSize(usize),
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
-enum HandType {
-    HighCard,
-    OnePair,
-    TwoPair,
-    ThreeOfAKind,
-    FullHouse,
-    FourOfAKind,
-    FiveOfAKind,
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::NoCards => write!(f, "no cards given"),
+            GameError::DuplicateCard(card) => write!(f, "card {:?} is given twice", card),
+            GameError::NonAsciiCard(card) => write!(f, "card {:?} is not ASCII", card),
+            GameError::UnknownWild(card) => write!(f, "wild card {:?} is not a card", card),
+            GameError::HandSize(size) => write!(f, "hands of {} cards are unsupported", size),
+        }
+    }
 }
 
-impl Hand {
-    fn best_hand_type(&self) -> HandType {
-        let mut counts = [0; 14];
+impl std::error::Error for GameError {}
 
-        for &card in self.cards.iter() {
-            let index = CARDS.iter().position(|&c| c == card).unwrap();
-            counts[index] += 1;
-        }
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    InvalidLine(String),
+    WrongHandSize { hand: String, expected: usize },
+    UnknownCard { hand: String, card: char },
+    InvalidBid(String),
+}
 
-        // If use jokers as wildcards
-        if self.joker {
-            // Get max position
-            let max_index = counts
-                .iter()
-                .enumerate()
-                .skip(1)
-                .max_by_key(|(_, &count)| count)
-                .unwrap()
-                .0;
-
-            counts[max_index] += counts[0];
-            counts[0] = 0;
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidLine(line) => write!(f, "invalid line: {:?}", line),
+            ParseError::WrongHandSize { hand, expected } => {
+                write!(f, "hand {:?} should have {} cards", hand, expected)
+            }
+            ParseError::UnknownCard { hand, card } => {
+                write!(f, "unknown card {:?} in hand {:?}", card, hand)
+            }
+            ParseError::InvalidBid(bid) => write!(f, "invalid bid: {:?}", bid),
         }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Number of bits needed to store values up to `max`.
+fn bits_for(max: usize) -> u32 {
+    (usize::BITS - max.leading_zeros()).max(1)
+}
 
-        counts.sort_unstable();
+/// The rules for ranking hands of cards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Game {
+    /// Rank of each ASCII card, from 0 for the weakest.
+    ranks: [Option<u8>; 128],
+    card_count: usize,
+    wild: bool,
+    hand_size: usize,
+}
 
-        match counts[13] {
-            1 => HandType::HighCard,
-            2 => {
-                if counts[12] == 2 {
-                    HandType::TwoPair
-                } else {
-                    HandType::OnePair
-                }
+impl Game {
+    /// Creates a game with `cards` from weakest to strongest. The `wild` card acts as
+    /// whichever card makes the strongest type of hand, but is weaker than every other
+    /// card when comparing hands of the same type.
+    pub fn new(cards: &str, wild: Option<char>, hand_size: usize) -> Result<Self, GameError> {
+        let mut ranks = [None; 128];
+        let mut card_count = 0;
+
+        // Wild cards are always ranked lowest
+        let wild_card = wild.into_iter();
+        let others = cards.chars().filter(|&card| Some(card) != wild);
+        for card in wild_card.chain(others) {
+            let rank = ranks
+                .get_mut(card as usize)
+                .ok_or(GameError::NonAsciiCard(card))?;
+            if rank.is_some() {
+                return Err(GameError::DuplicateCard(card));
             }
-            3 => {
-                if counts[12] == 2 {
-                    HandType::FullHouse
-                } else {
-                    HandType::ThreeOfAKind
-                }
+            *rank = Some(card_count as u8);
+            card_count += 1;
+        }
+
+        if let Some(wild) = wild {
+            if !cards.contains(wild) {
+                return Err(GameError::UnknownWild(wild));
             }
-            4 => HandType::FourOfAKind,
-            5 => HandType::FiveOfAKind,
-            _ => unreachable!(),
         }
+        if card_count == 0 {
+            return Err(GameError::NoCards);
+        }
+
+        let game = Self {
+            ranks,
+            card_count,
+            wild: wild.is_some(),
+            hand_size,
+        };
+        if hand_size == 0 || game.key_bits() > u128::BITS {
+            return Err(GameError::HandSize(hand_size));
+        }
+
+        Ok(game)
     }
 
-    fn card_rank(card: char) -> usize {
-        CARDS.iter().position(|c| card == *c).expect("Invalid card")
+    /// Camel Cards as in part 1.
+    pub fn camel_cards() -> Self {
+        Self::new("23456789TJQKA", None, 5).unwrap()
     }
-}
 
-impl Ord for Hand {
-    fn cmp(&self, other: &Self) -> Ordering {
-        let hand_ordering = self.best_hand_type().cmp(&other.best_hand_type());
-        if hand_ordering == Ordering::Equal {
-            for (&a, &b) in self.cards.iter().zip(other.cards.iter()) {
-                let card_ordering = Self::card_rank(a).cmp(&Self::card_rank(b));
-                if card_ordering != Ordering::Equal {
-                    return card_ordering;
-                }
-            }
+    /// Camel Cards with jokers wild, as in part 2.
+    pub fn jokers_wild() -> Self {
+        Self::new("23456789TJQKA", Some('J'), 5).unwrap()
+    }
+
+    fn card_bits(&self) -> u32 {
+        bits_for(self.card_count - 1)
+    }
+
+    fn count_bits(&self) -> u32 {
+        bits_for(self.hand_size)
+    }
+
+    fn key_bits(&self) -> u32 {
+        self.hand_size as u32 * (self.count_bits() + self.card_bits())
+    }
+
+    fn rank(&self, card: char) -> Option<u8> {
+        *self.ranks.get(card as usize)?
+    }
+
+    /// Sizes of the groups of matching cards in a hand from largest to smallest, with any
+    /// wild cards joining the largest group.
+    pub fn shape(&self, hand: &str) -> Result<Vec<u8>, ParseError> {
+        let mut counts = vec![0; self.card_count];
+        let mut size = 0;
+        for card in hand.chars() {
+            let rank = self.rank(card).ok_or_else(|| ParseError::UnknownCard {
+                hand: hand.to_string(),
+                card,
+            })?;
+            counts[rank as usize] += 1;
+            size += 1;
         }
-        hand_ordering
+        if size != self.hand_size {
+            return Err(ParseError::WrongHandSize {
+                hand: hand.to_string(),
+                expected: self.hand_size,
+            });
+        }
+
+        let wilds = if self.wild {
+            std::mem::take(&mut counts[0])
+        } else {
+            0
+        };
+        counts.sort_unstable_by(|a, b| b.cmp(a));
+        counts[0] += wilds;
+        counts.resize(self.hand_size, 0);
+
+        Ok(counts)
     }
-}
 
-impl PartialOrd for Hand {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+    /// Packs a hand into a key that sorts by the type of hand, then the rank of each card
+    /// in turn.
+    pub fn score(&self, hand: &str) -> Result<u128, ParseError> {
+        let count_bits = self.count_bits();
+        let card_bits = self.card_bits();
+
+        let shape = self
+            .shape(hand)?
+            .into_iter()
+            .fold(0, |key, count| key << count_bits | count as u128);
+
+        // Cards are known to be valid after finding the shape
+        Ok(hand.chars().fold(shape, |key, card| {
+            key << card_bits | self.rank(card).unwrap() as u128
+        }))
     }
 }
 
-impl PartialEq for Hand {
-    fn eq(&self, other: &Self) -> bool {
-        self.cards == other.cards
-    }
+struct Hand {
+    key: u128,
+    bid: u64,
 }
 
-fn parse_hands(input: &str, joker: bool) -> Vec<Hand> {
+fn parse_hands(input: &str, game: &Game) -> Result<Vec<Hand>, ParseError> {
     input
         .trim()
         .lines()
         .map(|line| {
-            let (cards, bid) = line.split_once(' ').unwrap();
-
-            // Collect into temp vec before convert to array
-            let cards: [char; 5] = cards
-                .chars()
-                .collect::<Vec<char>>()
-                .try_into()
-                .expect("Expect 5 cards");
+            let (cards, bid) = line
+                .split_once(' ')
+                .ok_or_else(|| ParseError::InvalidLine(line.to_string()))?;
 
-            let bid = bid.parse::<u32>().expect("Invalid bid");
+            let key = game.score(cards)?;
+            let bid = bid
+                .trim()
+                .parse()
+                .map_err(|_| ParseError::InvalidBid(bid.to_string()))?;
 
-            Hand { cards, joker, bid }
+            Ok(Hand { key, bid })
         })
         .collect()
 }
 
-pub fn part1(input: &str) -> String {
-    let mut hands = parse_hands(input, false);
+/// Sum of each hand's bid multiplied by its rank among all the hands.
+pub fn total_winnings(input: &str, game: &Game) -> Result<u64, ParseError> {
+    let mut hands = parse_hands(input, game)?;
 
     // Sort hands
-    hands.sort_unstable();
+    hands.sort_unstable_by_key(|hand| hand.key);
 
-    hands
+    Ok(hands
         .iter()
         .enumerate()
-        .map(|(i, hand)| (i + 1) as u32 * hand.bid)
-        .sum::<u32>()
+        .map(|(i, hand)| (i + 1) as u64 * hand.bid)
+        .sum())
+}
+
+pub fn part1(input: &str) -> String {
+    total_winnings(input, &Game::camel_cards())
+        .expect("Invalid hands")
         .to_string()
 }
 
 pub fn part2(input: &str) -> String {
-    let mut hands = parse_hands(input, true);
-
-    // Sort hands
-    hands.sort_unstable();
-
-    hands
-        .iter()
-        .enumerate()
-        .map(|(i, hand)| (i + 1) as u32 * hand.bid)
-        .sum::<u32>()
+    total_winnings(input, &Game::jokers_wild())
+        .expect("Invalid hands")
         .to_string()
 }
 
@@ -165,97 +249,119 @@ QQQJA 483
 
     #[test]
     fn test_hand_type() {
-        let test_hands: Vec<_> = TEST_INPUT
+        let hands: Vec<_> = TEST_INPUT
             .trim()
             .lines()
-            .map(|line| {
-                let cards = line.split_once(' ').unwrap().0;
-
-                let cards: [char; 5] = cards
-                    .chars()
-                    .collect::<Vec<char>>()
-                    .try_into()
-                    .expect("Expect 5 cards");
-
-                Hand {
-                    cards,
-                    joker: false,
-                    bid: 0,
-                }
-            })
+            .map(|line| line.split_once(' ').unwrap().0)
             .collect();
 
+        let game = Game::camel_cards();
         assert_eq!(
-            test_hands
+            hands
                 .iter()
-                .map(Hand::best_hand_type)
+                .map(|hand| game.shape(hand).unwrap())
                 .collect::<Vec<_>>(),
             [
-                HandType::OnePair,
-                HandType::ThreeOfAKind,
-                HandType::TwoPair,
-                HandType::TwoPair,
-                HandType::ThreeOfAKind,
+                vec![2, 1, 1, 1, 0],
+                vec![3, 1, 1, 0, 0],
+                vec![2, 2, 1, 0, 0],
+                vec![2, 2, 1, 0, 0],
+                vec![3, 1, 1, 0, 0],
             ]
         );
 
+        let game = Game::jokers_wild();
         assert_eq!(
-            test_hands
+            hands
                 .iter()
-                .map(|hand| Hand {
-                    cards: hand.cards,
-                    joker: true,
-                    bid: 0
-                })
-                .map(|hand| hand.best_hand_type())
+                .map(|hand| game.shape(hand).unwrap())
                 .collect::<Vec<_>>(),
             [
-                HandType::OnePair,
-                HandType::FourOfAKind,
-                HandType::TwoPair,
-                HandType::FourOfAKind,
-                HandType::FourOfAKind,
+                vec![2, 1, 1, 1, 0],
+                vec![4, 1, 0, 0, 0],
+                vec![2, 2, 1, 0, 0],
+                vec![4, 1, 0, 0, 0],
+                vec![4, 1, 0, 0, 0],
             ]
         );
+        assert_eq!(game.shape("JJJJJ").unwrap(), [5, 0, 0, 0, 0]);
     }
 
     #[test]
     fn test_cmp_hands() {
-        let a = Hand {
-            cards: ['3', '3', '3', '3', '2'],
-            joker: false,
-            bid: 0,
-        };
-        let b = Hand {
-            cards: ['2', 'A', 'A', 'A', 'A'],
-            joker: false,
-            bid: 0,
-        };
-        assert_eq!(a.cmp(&b), Ordering::Greater);
+        let game = Game::camel_cards();
+        let score = |hand| game.score(hand).unwrap();
+
+        assert!(score("33332") > score("2AAAA"));
+        assert!(score("77888") > score("77788"));
+        assert!(score("KK677") > score("KTJJT"));
+        assert!(score("AAAAA") > score("KKKKK"));
+        assert!(score("22223") > score("AAAKK"));
+
+        let game = Game::jokers_wild();
+        let score = |hand| game.score(hand).unwrap();
+
+        assert!(score("KTJJT") > score("QQQJA"));
+        assert!(score("22222") > score("JJJJJ"));
+        assert!(score("JJJJJ") > score("AAAAK"));
+        assert!(score("JKKQA") > score("2345J"));
+        assert!(score("2345J") > score("23456"));
+    }
 
-        let a = Hand {
-            cards: ['7', '7', '8', '8', '8'],
-            joker: false,
-            bid: 0,
-        };
-        let b = Hand {
-            cards: ['7', '7', '7', '8', '8'],
-            joker: false,
-            bid: 0,
-        };
-        assert_eq!(a.cmp(&b), Ordering::Greater);
+    #[test]
+    fn test_variants() {
+        // Wild aces in three card hands
+        let game = Game::new("abcde", Some('e'), 3).unwrap();
+        let score = |hand| game.score(hand).unwrap();
+        assert_eq!(game.shape("aea").unwrap(), [3, 0, 0]);
+        assert!(score("aea") > score("ddc"));
+        assert!(score("ddc") > score("ecb"));
+        assert!(score("ecb") > score("dcb"));
+        assert!(score("bbe") > score("bba"));
+        assert!(score("bbe") < score("bbb"));
 
-        let a = Hand {
-            cards: ['K', 'K', '6', '7', '7'],
-            joker: false,
-            bid: 0,
-        };
-        let b = Hand {
-            cards: ['K', 'T', 'J', 'J', 'T'],
-            joker: false,
-            bid: 0,
-        };
-        assert_eq!(a.cmp(&b), Ordering::Greater);
+        assert_eq!(
+            total_winnings("cc 1\nba 10\nab 100\n", &Game::new("abc", None, 2).unwrap()),
+            Ok(100 + 2 * 10 + 3)
+        );
+
+        // Long hands still fit into the key
+        let game = Game::new("0123456789", Some('0'), 12).unwrap();
+        assert!(game.score("000000000001").unwrap() > game.score("999999999998").unwrap());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Game::new("ABCA", None, 5),
+            Err(GameError::DuplicateCard('A'))
+        );
+        assert_eq!(
+            Game::new("ABC", Some('D'), 5),
+            Err(GameError::UnknownWild('D'))
+        );
+        assert_eq!(Game::new("AB", None, 0), Err(GameError::HandSize(0)));
+        assert_eq!(Game::new("AB", None, 100), Err(GameError::HandSize(100)));
+
+        let game = Game::camel_cards();
+        assert_eq!(
+            game.score("AAAA"),
+            Err(ParseError::WrongHandSize {
+                hand: "AAAA".to_string(),
+                expected: 5
+            })
+        );
+        assert_eq!(
+            total_winnings("AAAA1 5", &game),
+            Err(ParseError::UnknownCard {
+                hand: "AAAA1".to_string(),
+                card: '1'
+            })
+        );
+        assert_eq!(
+            total_winnings("AAAAK x", &game),
+            Err(ParseError::InvalidBid("x".to_string()))
+        );
     }
 
     #[test]
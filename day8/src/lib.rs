@@ -1,91 +1,326 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
 
-#[derive(Debug, PartialEq, Eq, Hash)]
-struct Node<'a> {
-    name: &'a str,
-    left: &'a str,
-    right: &'a str,
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    MissingNodes,
+    NoInstructions,
+    InvalidInstruction(char),
+    InvalidNode(String),
+    DuplicateNode(String),
+    UnknownNode(String),
 }
 
-fn parse_nodes(nodes: &str) -> impl Iterator<Item = Node> {
-    nodes.trim().lines().map(|line| {
-        let (name, children) = line.split_once(" = ").unwrap();
-        let (left, right) = children[1..children.len() - 1].split_once(", ").unwrap();
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::MissingNodes => write!(f, "expected nodes after a blank line"),
+            ParseError::NoInstructions => write!(f, "no instructions given"),
+            ParseError::InvalidInstruction(ch) => write!(f, "invalid instruction {:?}", ch),
+            ParseError::InvalidNode(line) => write!(f, "invalid node: {:?}", line),
+            ParseError::DuplicateNode(name) => write!(f, "node {} defined twice", name),
+            ParseError::UnknownNode(name) => write!(f, "unknown node {}", name),
+        }
+    }
+}
 
-        Node { name, left, right }
-    })
+impl std::error::Error for ParseError {}
+
+/// Network of nodes with names interned to indices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Map<'a> {
+    /// Index into the children of a node: 0 for left and 1 for right.
+    instructions: Vec<u8>,
+    names: Vec<&'a str>,
+    children: Vec<[u32; 2]>,
 }
 
-fn gcd(mut a: usize, mut b: usize) -> usize {
-    while b != 0 {
-        let t = b;
-        b = a % b;
-        a = t;
+impl<'a> Map<'a> {
+    pub fn parse(input: &'a str) -> Result<Self, ParseError> {
+        let (instructions, nodes) = input
+            .trim()
+            .split_once("\n\n")
+            .ok_or(ParseError::MissingNodes)?;
+
+        let instructions = instructions
+            .trim()
+            .chars()
+            .map(|ch| match ch {
+                'L' => Ok(0),
+                'R' => Ok(1),
+                _ => Err(ParseError::InvalidInstruction(ch)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if instructions.is_empty() {
+            return Err(ParseError::NoInstructions);
+        }
+
+        let nodes = nodes
+            .lines()
+            .map(|line| {
+                let invalid = || ParseError::InvalidNode(line.to_string());
+                let (name, children) = line.split_once(" = ").ok_or_else(invalid)?;
+                let (left, right) = children
+                    .strip_prefix('(')
+                    .and_then(|children| children.strip_suffix(')'))
+                    .and_then(|children| children.split_once(", "))
+                    .ok_or_else(invalid)?;
+                Ok((name, [left, right]))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut indices = HashMap::new();
+        for (i, (name, _)) in nodes.iter().enumerate() {
+            if indices.insert(*name, i as u32).is_some() {
+                return Err(ParseError::DuplicateNode(name.to_string()));
+            }
+        }
+
+        let children = nodes
+            .iter()
+            .map(|(_, children)| {
+                let mut indexed = [0; 2];
+                for (index, child) in indexed.iter_mut().zip(children) {
+                    *index = *indices
+                        .get(child)
+                        .ok_or_else(|| ParseError::UnknownNode(child.to_string()))?;
+                }
+                Ok(indexed)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            instructions,
+            names: nodes.into_iter().map(|(name, _)| name).collect(),
+            children,
+        })
     }
-    a
-}
 
-fn lcm(numbers: &[usize]) -> usize {
-    let mut res = numbers[0];
+    /// Index of the node called `name`.
+    pub fn node(&self, name: &str) -> Result<u32, ParseError> {
+        self.names
+            .iter()
+            .position(|&node| node == name)
+            .map(|index| index as u32)
+            .ok_or_else(|| ParseError::UnknownNode(name.to_string()))
+    }
+
+    /// Steps to get from `start` to `end`, if it is ever reached.
+    pub fn steps(&self, start: u32, end: u32) -> Option<u64> {
+        // Every combination of node and instruction is seen before the path repeats
+        let limit = self.names.len() * self.instructions.len();
 
-    for x in numbers.iter().skip(1) {
-        res = x * res / gcd(*x, res);
+        let mut node = start;
+        for (step, &instruction) in self.instructions.iter().cycle().take(limit).enumerate() {
+            if node == end {
+                return Some(step as u64);
+            }
+            node = self.children[node as usize][instruction as usize];
+        }
+        None
     }
 
-    res
-}
+    /// Follows a path from `start` until it is at the same node and instruction as before,
+    /// noting the steps on which it is at a node in `targets`.
+    fn trajectory(&self, start: u32, targets: &[bool]) -> Trajectory {
+        let len = self.instructions.len();
+        let mut seen = vec![u64::MAX; self.names.len() * len];
+        let mut hits = Vec::new();
 
-pub fn part1(input: &str) -> String {
-    let (commands, nodes) = input.split_once("\n\n").unwrap();
+        let mut node = start as usize;
+        let mut instruction = 0;
+        for step in 0.. {
+            let state = node * len + instruction;
+            let first = seen[state];
+            if first != u64::MAX {
+                let (pre_period_hits, cycle_hits) = hits.iter().partition(|&&hit| hit < first);
+                return Trajectory {
+                    pre_period: first,
+                    period: step - first,
+                    pre_period_hits,
+                    cycle_hits,
+                };
+            }
+            seen[state] = step;
 
-    let commands: Vec<_> = commands.trim().chars().collect();
-    let nodes: HashMap<&str, Node> = parse_nodes(nodes).map(|node| (node.name, node)).collect();
+            if targets[node] {
+                hits.push(step);
+            }
 
-    let mut current_node = nodes.get("AAA").expect("Couldn't find AAA");
-    for (step, command) in commands.iter().cycle().enumerate() {
-        if current_node.name == "ZZZ" {
-            return step.to_string();
+            node = self.children[node][self.instructions[instruction] as usize] as usize;
+            instruction += 1;
+            if instruction == len {
+                instruction = 0;
+            }
         }
 
-        let next_node = match command {
-            'L' => current_node.left,
-            'R' => current_node.right,
-            _ => panic!("Bad command"),
-        };
-        current_node = nodes.get(next_node).expect("Node not found");
+        unreachable!()
     }
+}
 
-    panic!("Empty map")
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnalysisError {
+    NoStart,
+    NeverSynchronises,
+    Overflow,
 }
 
-pub fn part2(input: &str) -> String {
-    let (commands, nodes) = input.split_once("\n\n").unwrap();
+impl fmt::Display for AnalysisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnalysisError::NoStart => write!(f, "no nodes end with A"),
+            AnalysisError::NeverSynchronises => {
+                write!(f, "the ghosts are never all on nodes ending with Z")
+            }
+            AnalysisError::Overflow => write!(f, "the number of steps is too large"),
+        }
+    }
+}
 
-    let commands: Vec<_> = commands.trim().chars().collect();
-    let nodes: HashMap<&str, Node> = parse_nodes(nodes).map(|node| (node.name, node)).collect();
+impl std::error::Error for AnalysisError {}
 
-    // All nodes ending with A
-    let steps: Vec<_> = nodes
-        .values()
-        .filter(|node| node.name.ends_with('A'))
-        .map(|mut current_node| {
-            for (step, command) in commands.iter().cycle().enumerate() {
-                if current_node.name.ends_with('Z') {
-                    return step;
+/// Steps on which a ghost is on a node ending with Z.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trajectory {
+    /// Steps before the ghost starts repeating itself.
+    pub pre_period: u64,
+    /// Length of the repeating part.
+    pub period: u64,
+    /// Hits before the repeating part, which only happen once.
+    pub pre_period_hits: Vec<u64>,
+    /// Hits in the first pass of the repeating part, which repeat every period.
+    pub cycle_hits: Vec<u64>,
+}
+
+impl Trajectory {
+    fn hits(&self, step: u64) -> bool {
+        if step < self.pre_period {
+            self.pre_period_hits.contains(&step)
+        } else {
+            let offset = (step - self.pre_period) % self.period;
+            self.cycle_hits.contains(&(self.pre_period + offset))
+        }
+    }
+}
+
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
+}
+
+/// First step on which every ghost is on a node ending with Z.
+pub fn synchronise(trajectories: &[Trajectory]) -> Result<u64, AnalysisError> {
+    if trajectories.is_empty() {
+        return Err(AnalysisError::NoStart);
+    }
+
+    // Before every ghost is repeating, one of them can only be on a hit it makes once
+    let repeating = trajectories.iter().map(|t| t.pre_period).max().unwrap();
+    let mut early_hits: Vec<_> = trajectories
+        .iter()
+        .flat_map(|t| t.pre_period_hits.iter().copied())
+        .collect();
+    early_hits.sort_unstable();
+    if let Some(step) = early_hits
+        .into_iter()
+        .find(|&step| trajectories.iter().all(|t| t.hits(step)))
+    {
+        return Ok(step);
+    }
+
+    // Afterwards, combine the hits of each cycle with the Chinese remainder theorem. The
+    // solutions so far are every step congruent to one of `remainders` modulo `modulus`.
+    let mut remainders: HashSet<i128> = HashSet::from([0]);
+    let mut modulus: i128 = 1;
+
+    for trajectory in trajectories {
+        let period = trajectory.period as i128;
+        let (g, x, _) = extended_gcd(modulus, period);
+        let step = period / g;
+
+        let mut combined = HashSet::new();
+        for &remainder in remainders.iter() {
+            for &hit in trajectory.cycle_hits.iter() {
+                let offset = hit as i128 % period;
+                if (offset - remainder) % g != 0 {
+                    continue;
                 }
+                // Every value is below the moduli so far, but their products may not fit
+                let k = ((offset - remainder) / g % step)
+                    .checked_mul(x % step)
+                    .ok_or(AnalysisError::Overflow)?
+                    .rem_euclid(step);
+                let combined_remainder = modulus
+                    .checked_mul(k)
+                    .and_then(|shift| remainder.checked_add(shift))
+                    .ok_or(AnalysisError::Overflow)?;
+                combined.insert(combined_remainder);
+            }
+        }
 
-                let next_node = match command {
-                    'L' => current_node.left,
-                    'R' => current_node.right,
-                    _ => panic!("Bad command"),
-                };
-                current_node = nodes.get(next_node).expect("Node not found");
+        modulus = modulus.checked_mul(step).ok_or(AnalysisError::Overflow)?;
+        remainders = combined
+            .into_iter()
+            .map(|remainder| remainder % modulus)
+            .collect();
+    }
+
+    // Cycles only apply once every ghost is repeating
+    let repeating = repeating as i128;
+    let steps = remainders
+        .into_iter()
+        .map(|remainder| {
+            if remainder >= repeating {
+                return Ok(remainder);
             }
-            panic!("Empty map");
+            // Rounds up the number of cycles needed to reach the repeating part
+            (repeating - remainder)
+                .checked_add(modulus - 1)
+                .map(|gap| gap / modulus)
+                .and_then(|cycles_needed| cycles_needed.checked_mul(modulus))
+                .and_then(|shift| remainder.checked_add(shift))
+                .ok_or(AnalysisError::Overflow)
         })
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .min()
+        .ok_or(AnalysisError::NeverSynchronises)?;
+
+    u64::try_from(steps).map_err(|_| AnalysisError::Overflow)
+}
+
+/// Steps until every ghost starting on a node ending with A is on a node ending with Z.
+pub fn ghost_steps(map: &Map) -> Result<u64, AnalysisError> {
+    let targets: Vec<_> = map.names.iter().map(|name| name.ends_with('Z')).collect();
+
+    // All nodes ending with A
+    let trajectories: Vec<_> = (0..map.names.len())
+        .filter(|&node| map.names[node].ends_with('A'))
+        .map(|node| map.trajectory(node as u32, &targets))
         .collect();
 
-    lcm(&steps).to_string()
+    synchronise(&trajectories)
+}
+
+pub fn part1(input: &str) -> String {
+    let map = Map::parse(input).expect("Invalid map");
+    let start = map.node("AAA").expect("Invalid map");
+    let end = map.node("ZZZ").expect("Invalid map");
+
+    map.steps(start, end)
+        .expect("Never reaches ZZZ")
+        .to_string()
+}
+
+pub fn part2(input: &str) -> String {
+    let map = Map::parse(input).expect("Invalid map");
+    ghost_steps(&map).expect("Invalid map").to_string()
 }
 
 #[cfg(test)]
@@ -125,14 +360,155 @@ LR
 XXX = (XXX, XXX)
 "#;
 
+    /// Ghosts whose first hits don't line up with their cycles.
+    const GHOSTS_INPUT: &str = r#"
+L
+
+11A = (11Z, 11Z)
+11Z = (11B, 11B)
+11B = (11C, 11C)
+11C = (11Z, 11Z)
+22A = (22B, 22B)
+22B = (22Z, 22Z)
+22Z = (22B, 22B)
+33A = (33Z, 33Z)
+33Z = (33B, 33B)
+33B = (33B, 33B)
+44A = (44Z, 44Z)
+44Z = (44B, 44B)
+44B = (44Z, 44Z)
+55A = (55Z, 55Z)
+55Z = (55B, 55B)
+55B = (56Z, 56Z)
+56Z = (55C, 55C)
+55C = (55A, 55A)
+"#;
+
+    fn trajectories(names: &[&str]) -> Vec<Trajectory> {
+        let map = Map::parse(GHOSTS_INPUT).unwrap();
+        let targets: Vec<_> = map.names.iter().map(|name| name.ends_with('Z')).collect();
+
+        names
+            .iter()
+            .map(|name| map.trajectory(map.node(name).unwrap(), &targets))
+            .collect()
+    }
+
     #[test]
     fn test_part1() {
         assert_eq!(&part1(TEST_INPUT_1), "2");
         assert_eq!(&part1(TEST_INPUT_2), "6");
+
+        let map = Map::parse("L\n\nAAA = (AAA, ZZZ)\nZZZ = (ZZZ, ZZZ)").unwrap();
+        assert_eq!(map.steps(0, 1), None);
     }
 
     #[test]
     fn test_part2() {
         assert_eq!(&part2(TEST_INPUT_3), "6");
     }
+
+    #[test]
+    fn test_trajectory() {
+        assert_eq!(
+            trajectories(&["11A", "33A"]),
+            [
+                Trajectory {
+                    pre_period: 1,
+                    period: 3,
+                    pre_period_hits: vec![],
+                    cycle_hits: vec![1],
+                },
+                Trajectory {
+                    pre_period: 2,
+                    period: 1,
+                    pre_period_hits: vec![1],
+                    cycle_hits: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_synchronise() {
+        // 1, 4, 7, ... and 2, 4, 6, ...
+        assert_eq!(synchronise(&trajectories(&["11A", "22A"])), Ok(4));
+        // Only 1
+        assert_eq!(synchronise(&trajectories(&["11A", "33A"])), Ok(1));
+        assert_eq!(
+            synchronise(&trajectories(&["22A", "33A"])),
+            Err(AnalysisError::NeverSynchronises)
+        );
+        // 1, 3, 5, ...
+        assert_eq!(
+            synchronise(&trajectories(&["22A", "44A"])),
+            Err(AnalysisError::NeverSynchronises)
+        );
+        assert_eq!(synchronise(&trajectories(&["11A", "44A"])), Ok(1));
+        assert_eq!(
+            synchronise(&trajectories(&["11A", "22A", "44A"])),
+            Err(AnalysisError::NeverSynchronises)
+        );
+        // 1, 3, 6, 8, ... and 2, 4, 6, ...
+        assert_eq!(synchronise(&trajectories(&["55A", "22A"])), Ok(6));
+        assert_eq!(synchronise(&[]), Err(AnalysisError::NoStart));
+    }
+
+    #[test]
+    fn test_synchronise_overflow() {
+        let trajectory = |pre_period, period, hit| Trajectory {
+            pre_period,
+            period,
+            pre_period_hits: Vec::new(),
+            cycle_hits: vec![hit],
+        };
+
+        // Coprime periods near 2^64 combine to a step too large for an i128
+        assert_eq!(
+            synchronise(&[
+                trajectory(0, u64::MAX - 58, 0),
+                trajectory(0, u64::MAX - 82, 1)
+            ]),
+            Err(AnalysisError::Overflow)
+        );
+
+        // The combined period just fits, but rounding up to the repeating part doesn't
+        assert_eq!(
+            synchronise(&[
+                trajectory((1 << 63) + 1, (1 << 63) + 1, (1 << 63) + 1),
+                trajectory(0, u64::MAX - 1, 0)
+            ]),
+            Err(AnalysisError::Overflow)
+        );
+
+        // Periods of 2^31 - 1 and 2^31 combine to just under 2^62
+        assert_eq!(
+            synchronise(&[trajectory(0, (1 << 31) - 1, 1), trajectory(0, 1 << 31, 2)]),
+            Ok((1 << 62) - (1 << 32) + 2)
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Map::parse("LR\n\nAAA = (BBB, AAA)"),
+            Err(ParseError::UnknownNode("BBB".to_string()))
+        );
+        assert_eq!(
+            Map::parse("LR\n\nAAA = (AAA, AAA)\nAAA = (AAA, AAA)"),
+            Err(ParseError::DuplicateNode("AAA".to_string()))
+        );
+        assert_eq!(
+            Map::parse("LX\n\nAAA = (AAA, AAA)"),
+            Err(ParseError::InvalidInstruction('X'))
+        );
+        assert_eq!(
+            Map::parse("LR\n\nAAA = AAA, AAA"),
+            Err(ParseError::InvalidNode("AAA = AAA, AAA".to_string()))
+        );
+        assert_eq!(
+            Map::parse(TEST_INPUT_3).unwrap().node("AAA"),
+            Err(ParseError::UnknownNode("AAA".to_string()))
+        );
+    }
 }
@@ -1,84 +1,178 @@
-fn parse_sequence(line: &str, buf: &mut Vec<i64>) {
-    buf.clear();
-    buf.extend(
-        line.split_ascii_whitespace()
-            .map(|x| x.parse::<i64>().unwrap()),
-    );
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    InvalidNumber(String),
 }
 
-/// Generates coefficients for the nth difference operation based on Pascal's triangle.
-///
-/// Given an integer `n`, this function computes the coefficients used in the formula for the nth
-/// difference operation. The formula involves binomial coefficients with alternating signs,
-/// resembling a signed version of Pascal's triangle.
-fn generate_coefficients(n: usize, coefficient_buf: &mut Vec<i64>) {
-    coefficient_buf.clear();
-    coefficient_buf.push(1);
-
-    for k in 1..=n {
-        let next_coefficient = coefficient_buf[k - 1] * (n as i64 - k as i64 + 1) / k as i64;
-        coefficient_buf.push(next_coefficient);
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidNumber(number) => write!(f, "invalid number: {:?}", number),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExtrapolationError {
+    Empty,
+    /// The differences never become constant while there are still at least two of them,
+    /// so nothing shows the fitted polynomial describes the sequence.
+    NoConstantDifference,
+    Overflow,
+}
+
+impl fmt::Display for ExtrapolationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtrapolationError::Empty => write!(f, "sequence is empty"),
+            ExtrapolationError::NoConstantDifference => {
+                write!(f, "differences of the sequence never become constant")
+            }
+            ExtrapolationError::Overflow => write!(f, "arithmetic overflow"),
+        }
     }
+}
 
-    // Apply alternating signs
-    for coefficient in coefficient_buf.iter_mut().skip(1).step_by(2) {
-        *coefficient *= -1;
+impl std::error::Error for ExtrapolationError {}
+
+fn parse_sequence(line: &str, buf: &mut Vec<i64>) -> Result<(), ParseError> {
+    buf.clear();
+    for x in line.split_ascii_whitespace() {
+        let x = x
+            .parse::<i64>()
+            .map_err(|_| ParseError::InvalidNumber(x.to_string()))?;
+        buf.push(x);
     }
+    Ok(())
 }
 
-/// Interpolates a sequence of equally spaced points using a polynomial of degree at most n,
-/// where n is the length of the input sequence minus 1.
-/// The interpolation is based on the method of finite differences.
+/// A polynomial fitted to a sequence of equally spaced points by the method of finite
+/// differences.
 ///
-/// A polynomial y(x) of degree d defines a sequence of values at positive integer points,
-/// y_j = y(j), and the (d + 1)th difference of this sequence is identically zero:
-/// Δ^(d+1)y = 0.
+/// A polynomial y(x) of degree d defines a sequence of values at integer points,
+/// y_j = y(j), whose dth difference is constant. Given the first value of each row of
+/// differences, Newton's forward difference formula recovers the polynomial anywhere:
 ///
-/// Thus, given values y_0, ..., y_n at equally spaced points, where n = d + 1, we have:
-/// (-1)^n * y_0 + (-1)^(n-1) * C(n,1) * y_1 + ... - C(n,n-1) * y_(n-1) + y_n = 0.
-fn interpolate_sequence(sequence: &[i64], coefficient_buf: &mut Vec<i64>) -> i64 {
-    generate_coefficients(sequence.len(), coefficient_buf);
-
-    let interpolation = sequence
-        .iter()
-        .zip(coefficient_buf.iter().rev())
-        .map(|(x, coeff)| x * coeff)
-        .sum::<i64>();
-
-    -interpolation
+/// y(x) = Σ C(x, j) * Δ^j y_0 for j = 0..=d
+///
+/// where C(x, j) = x (x - 1) ... (x - j + 1) / j! also holds for negative x.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Polynomial {
+    /// First value of each row of differences, Δ^j y_0.
+    leading: Vec<i128>,
+    len: usize,
 }
 
-pub fn part1(input: &str) -> String {
-    // Allocated single buffers to be reused
-    let mut sequence_buf = Vec::new();
-    let mut coefficient_buf = Vec::new();
+impl Polynomial {
+    /// Fits the polynomial of lowest degree whose differences become constant, counting a
+    /// single difference as constant.
+    pub fn fit(sequence: &[i64]) -> Result<Self, ExtrapolationError> {
+        Self::fit_differences(sequence, false)
+    }
 
-    input
-        .trim()
-        .lines()
-        .map(|line| {
-            parse_sequence(line, &mut sequence_buf);
-            interpolate_sequence(&sequence_buf, &mut coefficient_buf)
-        })
-        .sum::<i64>()
-        .to_string()
+    /// Fits the polynomial of lowest degree whose differences become constant while there
+    /// are still at least two of them, which fails if the sequence is too short to show
+    /// its degree.
+    pub fn fit_exact(sequence: &[i64]) -> Result<Self, ExtrapolationError> {
+        Self::fit_differences(sequence, true)
+    }
+
+    fn fit_differences(sequence: &[i64], exact: bool) -> Result<Self, ExtrapolationError> {
+        if sequence.is_empty() {
+            return Err(ExtrapolationError::Empty);
+        }
+
+        let mut row: Vec<i128> = sequence.iter().map(|&x| x as i128).collect();
+        let mut leading = Vec::new();
+
+        loop {
+            if exact && row.len() < 2 {
+                return Err(ExtrapolationError::NoConstantDifference);
+            }
+
+            leading.push(row[0]);
+            if row.iter().all(|&x| x == row[0]) {
+                return Ok(Self {
+                    leading,
+                    len: sequence.len(),
+                });
+            }
+
+            // Take differences in place
+            for i in 0..row.len() - 1 {
+                row[i] = row[i + 1]
+                    .checked_sub(row[i])
+                    .ok_or(ExtrapolationError::Overflow)?;
+            }
+            row.pop();
+        }
+    }
+
+    pub fn degree(&self) -> usize {
+        self.leading.len() - 1
+    }
+
+    /// Value of the polynomial at `x`, where the fitted sequence starts at 0.
+    pub fn value_at(&self, x: i128) -> Result<i128, ExtrapolationError> {
+        let mut value: i128 = 0;
+        let mut binomial: i128 = 1;
+
+        for (j, &difference) in self.leading.iter().enumerate() {
+            if j > 0 {
+                // C(x, j) = C(x, j - 1) * (x - j + 1) / j, which always divides exactly
+                binomial = (x - j as i128 + 1)
+                    .checked_mul(binomial)
+                    .ok_or(ExtrapolationError::Overflow)?
+                    / j as i128;
+            }
+            value = binomial
+                .checked_mul(difference)
+                .and_then(|term| value.checked_add(term))
+                .ok_or(ExtrapolationError::Overflow)?;
+        }
+
+        Ok(value)
+    }
+
+    /// Value `steps` after the last value of the fitted sequence.
+    pub fn forward(&self, steps: u64) -> Result<i128, ExtrapolationError> {
+        self.value_at(self.len as i128 - 1 + steps as i128)
+    }
+
+    /// Value `steps` before the first value of the fitted sequence.
+    pub fn backward(&self, steps: u64) -> Result<i128, ExtrapolationError> {
+        self.value_at(-(steps as i128))
+    }
 }
 
-pub fn part2(input: &str) -> String {
-    // Allocated single buffers to be reused
+/// Sums the value of every sequence in the input extrapolated with `extrapolate`.
+fn sum_extrapolations(
+    input: &str,
+    extrapolate: impl Fn(&Polynomial) -> Result<i128, ExtrapolationError>,
+) -> i128 {
+    // Allocated single buffer to be reused
     let mut sequence_buf = Vec::new();
-    let mut coefficient_buf = Vec::new();
 
     input
         .trim()
         .lines()
         .map(|line| {
-            parse_sequence(line, &mut sequence_buf);
-            sequence_buf.reverse();
-            interpolate_sequence(&sequence_buf, &mut coefficient_buf)
+            parse_sequence(line, &mut sequence_buf).expect("Invalid sequence");
+            let polynomial = Polynomial::fit(&sequence_buf).expect("Can't fit sequence");
+            extrapolate(&polynomial).expect("Can't extrapolate sequence")
         })
-        .sum::<i64>()
-        .to_string()
+        .sum::<i128>()
+}
+
+pub fn part1(input: &str) -> String {
+    sum_extrapolations(input, |polynomial| polynomial.forward(1)).to_string()
+}
+
+pub fn part2(input: &str) -> String {
+    sum_extrapolations(input, |polynomial| polynomial.backward(1)).to_string()
 }
 
 #[cfg(test)]
@@ -100,4 +194,94 @@ mod tests {
     fn test_part2() {
         assert_eq!(&part2(TEST_INPUT), "2");
     }
+
+    #[test]
+    fn test_extrapolate() {
+        let linear = Polynomial::fit(&[0, 3, 6, 9, 12, 15]).unwrap();
+        assert_eq!(linear.degree(), 1);
+        assert_eq!(linear.forward(0), Ok(15));
+        assert_eq!(linear.forward(10), Ok(45));
+        assert_eq!(linear.backward(3), Ok(-9));
+
+        let cubic = Polynomial::fit(&[10, 13, 16, 21, 30, 45]).unwrap();
+        assert_eq!(cubic.degree(), 3);
+        assert_eq!(cubic.forward(1), Ok(68));
+        assert_eq!(cubic.forward(2), Ok(101));
+        assert_eq!(cubic.backward(1), Ok(5));
+        assert_eq!(cubic.backward(2), Ok(-4));
+
+        // Differences that are shown to be constant fit the same either way
+        for sequence in [
+            &[0, 3, 6, 9, 12, 15][..],
+            &[10, 13, 16, 21, 30, 45],
+            &[7, 7],
+        ] {
+            assert_eq!(Polynomial::fit_exact(sequence), Polynomial::fit(sequence));
+        }
+
+        let constant = Polynomial::fit(&[7, 7]).unwrap();
+        assert_eq!(constant.degree(), 0);
+        assert_eq!(constant.backward(1_000_000), Ok(7));
+
+        let single = Polynomial::fit(&[5]).unwrap();
+        assert_eq!(single.degree(), 0);
+        assert_eq!(single.forward(1), Ok(5));
+        assert_eq!(single.backward(1), Ok(5));
+
+        // Only constant once a single difference is left
+        let line = Polynomial::fit(&[1, 3]).unwrap();
+        assert_eq!(line.degree(), 1);
+        assert_eq!(line.forward(1), Ok(5));
+        assert_eq!(line.backward(1), Ok(-1));
+
+        let powers = Polynomial::fit(&[1, 2, 4, 8, 16]).unwrap();
+        assert_eq!(powers.degree(), 4);
+        assert_eq!(powers.forward(1), Ok(31));
+        assert_eq!(powers.backward(1), Ok(1));
+
+        // x^2 from x = -2
+        let square = Polynomial::fit(&[4, 1, 0, 1, 4]).unwrap();
+        assert_eq!(square.degree(), 2);
+        assert_eq!(
+            square.forward(1_000_000_000_000),
+            Ok(1_000_000_000_002_i128.pow(2))
+        );
+        assert_eq!(square.backward(1), Ok(9));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Polynomial::fit(&[]), Err(ExtrapolationError::Empty));
+        assert_eq!(Polynomial::fit_exact(&[]), Err(ExtrapolationError::Empty));
+        assert_eq!(
+            Polynomial::fit_exact(&[5]),
+            Err(ExtrapolationError::NoConstantDifference)
+        );
+        assert_eq!(
+            Polynomial::fit_exact(&[1, 3]),
+            Err(ExtrapolationError::NoConstantDifference)
+        );
+        assert_eq!(
+            Polynomial::fit_exact(&[1, 2, 4, 8, 16]),
+            Err(ExtrapolationError::NoConstantDifference)
+        );
+
+        // Differences double in size every row
+        let alternating: Vec<_> = (0..80)
+            .map(|i| if i % 2 == 0 { i64::MAX } else { i64::MIN })
+            .collect();
+        assert_eq!(
+            Polynomial::fit(&alternating),
+            Err(ExtrapolationError::Overflow)
+        );
+
+        let cubic = Polynomial::fit(&[0, 1, 8, 27, 64]).unwrap();
+        assert_eq!(cubic.forward(1 << 50), Err(ExtrapolationError::Overflow));
+
+        let mut buf = Vec::new();
+        assert_eq!(
+            parse_sequence("1 2 x", &mut buf),
+            Err(ParseError::InvalidNumber("x".to_string()))
+        );
+    }
 }
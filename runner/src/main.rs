@@ -10,6 +10,15 @@ struct Solution {
     part2: Option<fn(input: &str) -> String>,
 }
 
+type ConfigurablePart = fn(input: &str, options: &[(&str, &str)]) -> Result<String, String>;
+
+/// Parts which accept `KEY=VALUE` options from the command line.
+struct ConfigurableSolution {
+    day: usize,
+    part1: Option<ConfigurablePart>,
+    part2: Option<ConfigurablePart>,
+}
+
 macro_rules! solutions {
     ($($part1:expr, $part2:expr),* $(,)?) => {
         [
@@ -49,23 +58,50 @@ const SOLUTIONS: [Solution; 25] = solutions!(
     Some(day25::part1), Some(day25::part2),
 );
 
+macro_rules! configurable {
+    ($part:path) => {
+        Some(|input, options| $part(input, options).map_err(|err| err.to_string()))
+    };
+}
+
+const CONFIGURABLE_SOLUTIONS: [ConfigurableSolution; 3] = [
+    ConfigurableSolution {
+        day: 2,
+        part1: configurable!(day2::part1_with_options),
+        part2: None,
+    },
+    ConfigurableSolution {
+        day: 17,
+        part1: configurable!(day17::part1_with_options),
+        part2: configurable!(day17::part2_with_options),
+    },
+    ConfigurableSolution {
+        day: 24,
+        part1: configurable!(day24::part1_with_options),
+        part2: None,
+    },
+];
+
 fn print_usage(name: &str) {
-    eprintln!("Usage: {} <DAY> <INPUT>", name);
+    eprintln!("Usage: {} <DAY> <INPUT> [KEY=VALUE]...", name);
 }
 
-fn run_part(input: &str, func: Option<fn(input: &str) -> String>) {
+fn run_part(func: Option<impl Fn() -> Result<String, String>>) {
     if let Some(func) = func {
         let start = Instant::now();
 
         // Run solution REPEAT times
         let solution = (0..REPEATS)
-            .map(|_| func(input))
-            .last()
+            .map(|_| func())
+            .reduce(|_, solution| solution)
             .expect("REPEATS must be greater than 0");
 
         let duration = start.elapsed() / REPEATS;
 
-        println!("Solution: {}", solution);
+        match solution {
+            Ok(solution) => println!("Solution: {}", solution),
+            Err(err) => println!("Error: {}", err),
+        }
 
         print_elapsed_time(duration);
     } else {
@@ -113,17 +149,40 @@ fn main() -> Result<ExitCode, std::io::Error> {
         return Ok(ExitCode::FAILURE);
     }
 
+    // Split options into keys and values
+    let mut options = Vec::new();
+    for option in &args[3..] {
+        let Some((key, value)) = option.split_once('=') else {
+            eprintln!("Invalid option: {}", option);
+            print_usage(&args[0]);
+            return Ok(ExitCode::FAILURE);
+        };
+        options.push((key, value));
+    }
+
     // Get solution from table
     let solution = &SOLUTIONS[day - 1];
+    let configurable = CONFIGURABLE_SOLUTIONS.iter().find(|s| s.day == day);
+    if !options.is_empty() && configurable.is_none() {
+        eprintln!("Day {} doesn't take options", day);
+        return Ok(ExitCode::FAILURE);
+    }
 
     // Read input into memory
     let input = std::fs::read_to_string(input_path)?;
-
-    println!("==== Part 1 ====");
-    run_part(&input, solution.part1);
-
-    println!("==== Part 2 ====");
-    run_part(&input, solution.part2);
+    let input = input.as_str();
+
+    let parts = [
+        (solution.part1, configurable.and_then(|s| s.part1)),
+        (solution.part2, configurable.and_then(|s| s.part2)),
+    ];
+    for (i, (part, configurable_part)) in parts.into_iter().enumerate() {
+        println!("==== Part {} ====", i + 1);
+        match configurable_part {
+            Some(part) if !options.is_empty() => run_part(Some(|| part(input, &options))),
+            _ => run_part(part.map(|part| move || Ok(part(input)))),
+        }
+    }
 
     Ok(ExitCode::SUCCESS)
 }